@@ -0,0 +1,88 @@
+
+extern crate rctree;
+extern crate serde_json;
+
+use rctree::Node;
+
+fn tree() -> Node<i32> {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    node1.append(node2.clone());
+    node2.append(Node::new(3));
+    node1.append(Node::new(4));
+    node1.append(Node::new(5));
+    node1
+}
+
+#[test]
+fn serialize_1() {
+    assert_eq!(
+        serde_json::to_string(&tree()).unwrap(),
+        r#"{"data":1,"children":[{"data":2,"children":[{"data":3,"children":[]}]},{"data":4,"children":[]},{"data":5,"children":[]}]}"#
+    );
+
+    // A subtree is serialized on its own.
+    let node2 = tree().first_child().unwrap();
+    assert_eq!(
+        serde_json::to_string(&node2).unwrap(),
+        r#"{"data":2,"children":[{"data":3,"children":[]}]}"#
+    );
+}
+
+#[test]
+fn deserialize_1() {
+    let json = serde_json::to_string(&tree()).unwrap();
+    let node: Node<i32> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(
+        node.descendants().map(|n| *n.borrow()).collect::<Vec<_>>(),
+        [1, 2, 3, 4, 5]
+    );
+    assert_eq!(*node.last_child().unwrap().borrow(), 5);
+    assert_eq!(serde_json::to_string(&node).unwrap(), json);
+}
+
+#[test]
+fn deserialize_2() {
+    // Field order does not matter.
+    let node: Node<String> = serde_json::from_str(
+        r#"{"children":[{"data":"b","children":[]},{"children":[],"data":"c"}],"data":"a"}"#,
+    )
+    .unwrap();
+    assert_eq!(*node.borrow(), "a");
+    assert_eq!(*node.first_child().unwrap().borrow(), "b");
+    assert_eq!(*node.last_child().unwrap().borrow(), "c");
+
+    // Structs can be represented as sequences too.
+    let node: Node<i32> = serde_json::from_str("[1,[[2,[]]]]").unwrap();
+    assert_eq!(*node.first_child().unwrap().borrow(), 2);
+
+    assert!(serde_json::from_str::<Node<i32>>(r#"{"data":1}"#).is_err());
+    assert!(serde_json::from_str::<Node<i32>>(r#"{"children":[]}"#).is_err());
+    assert!(serde_json::from_str::<Node<i32>>(r#"{"data":1,"children":[],"x":0}"#).is_err());
+    assert!(serde_json::from_str::<Node<i32>>(r#"{"data":1,"data":2,"children":[]}"#).is_err());
+}
+
+#[test]
+fn serde_deep() {
+    // serde_json limits the nesting to 128 levels, and each tree level takes two.
+    let root = Node::new(0);
+    let mut parent = root.clone();
+    for i in 1..60 {
+        let node = Node::new(i);
+        parent.append(node.clone());
+        parent = node;
+    }
+
+    let json = serde_json::to_string(&root).unwrap();
+    let node: Node<i32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(node.descendants().count(), 60);
+    assert_eq!(node.descendants().last().map(|n| *n.borrow()), Some(59));
+
+    // Deeper input is rejected and the partially built tree is dropped.
+    let mut json = String::new();
+    for _ in 0..100_000 {
+        json.push_str(r#"{"data":0,"children":["#);
+    }
+    assert!(serde_json::from_str::<Node<i32>>(&json).is_err());
+}
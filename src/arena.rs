@@ -0,0 +1,896 @@
+/*!
+An arena-backed version of the tree.
+
+`Tree<T>` stores all of its nodes in a single contiguous `Vec`
+and addresses them with `NodeId` handles instead of reference counting each node.
+This avoids per-node allocations and reference-count updates during traversals,
+at the cost of passing the tree around along with node ids.
+
+The API mirrors `Node`, but the methods are on `Tree` and take `NodeId`s.
+Nodes are not freed when they are detached, only when they are removed with `Tree::remove`.
+
+Node ids are *generational*: a removed node's id stays invalid
+even after its slot is reused by a new node.
+*/
+
+use std::ops::{Index, IndexMut};
+
+use super::Node;
+
+/// A handle to a node in a `Tree`.
+///
+/// A `NodeId` is only meaningful for the tree that created it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+/// A tree with all nodes stored in a single arena.
+#[derive(Clone)]
+pub struct Tree<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    generation: u32,
+    node: Option<NodeData<T>>,
+}
+
+// Links are stored as plain indices, since they always point to live nodes.
+#[derive(Clone)]
+struct NodeData<T> {
+    parent: Option<usize>,
+    first_child: Option<usize>,
+    last_child: Option<usize>,
+    previous_sibling: Option<usize>,
+    next_sibling: Option<usize>,
+    data: T,
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Tree::new()
+    }
+}
+
+impl<T> Index<NodeId> for Tree<T> {
+    type Output = T;
+
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    fn index(&self, id: NodeId) -> &T {
+        &self.node(id).data
+    }
+}
+
+impl<T> IndexMut<NodeId> for Tree<T> {
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    fn index_mut(&mut self, id: NodeId) -> &mut T {
+        &mut self.node_mut(id).data
+    }
+}
+
+impl<T> Tree<T> {
+    /// Creates a new, empty tree.
+    pub fn new() -> Tree<T> {
+        Tree {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Creates a new, empty tree with space for at least `capacity` nodes.
+    pub fn with_capacity(capacity: usize) -> Tree<T> {
+        Tree {
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of nodes in the tree, including detached ones.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if `id` is a valid node of this tree.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Creates a new, detached node from its associated data.
+    pub fn new_node(&mut self, data: T) -> NodeId {
+        let node = NodeData {
+            parent: None,
+            first_child: None,
+            last_child: None,
+            previous_sibling: None,
+            next_sibling: None,
+            data,
+        };
+
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.node = Some(node);
+                NodeId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    node: Some(node),
+                });
+                NodeId {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Returns a shared reference to a node's data, unless `id` is invalid.
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.get_node(id).map(|node| &node.data)
+    }
+
+    /// Returns a unique/mutable reference to a node's data, unless `id` is invalid.
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.get_node_mut(id).map(|node| &mut node.data)
+    }
+
+    fn get_node(&self, id: NodeId) -> Option<&NodeData<T>> {
+        let slot = self.slots.get(id.index)?;
+        if slot.generation == id.generation {
+            slot.node.as_ref()
+        } else {
+            None
+        }
+    }
+
+    fn get_node_mut(&mut self, id: NodeId) -> Option<&mut NodeData<T>> {
+        let slot = self.slots.get_mut(id.index)?;
+        if slot.generation == id.generation {
+            slot.node.as_mut()
+        } else {
+            None
+        }
+    }
+
+    fn node(&self, id: NodeId) -> &NodeData<T> {
+        self.get_node(id).expect("invalid node id")
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut NodeData<T> {
+        self.get_node_mut(id).expect("invalid node id")
+    }
+
+    fn at(&self, index: usize) -> &NodeData<T> {
+        self.slots[index].node.as_ref().unwrap()
+    }
+
+    fn at_mut(&mut self, index: usize) -> &mut NodeData<T> {
+        self.slots[index].node.as_mut().unwrap()
+    }
+
+    fn id(&self, index: usize) -> NodeId {
+        NodeId {
+            index,
+            generation: self.slots[index].generation,
+        }
+    }
+
+    fn checked_index(&self, id: NodeId) -> usize {
+        self.node(id);
+        id.index
+    }
+
+    /// Returns a parent node, unless this node is the root of the tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent.map(|index| self.id(index))
+    }
+
+    /// Returns a first child of this node, unless it has no child.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).first_child.map(|index| self.id(index))
+    }
+
+    /// Returns a last child of this node, unless it has no child.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn last_child(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).last_child.map(|index| self.id(index))
+    }
+
+    /// Returns the previous sibling of this node, unless it is a first child.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).previous_sibling.map(|index| self.id(index))
+    }
+
+    /// Returns the next sibling of this node, unless it is a last child.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).next_sibling.map(|index| self.id(index))
+    }
+
+    /// Returns `true` if this node has children nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn has_children(&self, id: NodeId) -> bool {
+        self.node(id).first_child.is_some()
+    }
+
+    /// Returns an iterator of nodes to this node and its ancestors.
+    ///
+    /// Includes the current node.
+    pub fn ancestors<'a>(&'a self, id: NodeId) -> Ancestors<'a, T> {
+        Ancestors {
+            tree: self,
+            next: Some(id),
+        }
+    }
+
+    /// Returns an iterator of nodes to this node and the siblings before it.
+    ///
+    /// Includes the current node.
+    pub fn preceding_siblings<'a>(&'a self, id: NodeId) -> PrecedingSiblings<'a, T> {
+        PrecedingSiblings {
+            tree: self,
+            next: Some(id),
+        }
+    }
+
+    /// Returns an iterator of nodes to this node and the siblings after it.
+    ///
+    /// Includes the current node.
+    pub fn following_siblings<'a>(&'a self, id: NodeId) -> FollowingSiblings<'a, T> {
+        FollowingSiblings {
+            tree: self,
+            next: Some(id),
+        }
+    }
+
+    /// Returns an iterator of nodes to this node's children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn children<'a>(&'a self, id: NodeId) -> Children<'a, T> {
+        Children {
+            tree: self,
+            next: self.first_child(id),
+            next_back: self.last_child(id),
+        }
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order.
+    ///
+    /// Includes the current node.
+    pub fn descendants<'a>(&'a self, id: NodeId) -> Descendants<'a, T> {
+        Descendants(self.traverse(id))
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order.
+    pub fn traverse<'a>(&'a self, id: NodeId) -> Traverse<'a, T> {
+        Traverse {
+            tree: self,
+            root: id,
+            next: Some(NodeEdge::Start(id)),
+            next_back: Some(NodeEdge::End(id)),
+        }
+    }
+
+    /// Detaches a node from its parent and siblings. Children are not affected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn detach(&mut self, id: NodeId) {
+        let index = self.checked_index(id);
+        self.detach_index(index);
+    }
+
+    fn detach_index(&mut self, index: usize) {
+        let (parent, previous_sibling, next_sibling) = {
+            let node = self.at_mut(index);
+            (
+                node.parent.take(),
+                node.previous_sibling.take(),
+                node.next_sibling.take(),
+            )
+        };
+
+        match next_sibling {
+            Some(next_sibling) => self.at_mut(next_sibling).previous_sibling = previous_sibling,
+            None => {
+                if let Some(parent) = parent {
+                    self.at_mut(parent).last_child = previous_sibling;
+                }
+            }
+        }
+
+        match previous_sibling {
+            Some(previous_sibling) => self.at_mut(previous_sibling).next_sibling = next_sibling,
+            None => {
+                if let Some(parent) = parent {
+                    self.at_mut(parent).first_child = next_sibling;
+                }
+            }
+        }
+    }
+
+    // A debug-only check that an insertion will not create a cycle.
+    //
+    // A leaf cannot be an ancestor, so building a tree node by node
+    // does not walk up the whole tree each time.
+    fn debug_assert_not_ancestor(&self, id: NodeId, new_node: NodeId) {
+        debug_assert!(
+            !self.has_children(new_node)
+                || !self
+                    .ancestors(id)
+                    .skip(1)
+                    .any(|ancestor| ancestor == new_node),
+            "a node cannot be inserted into its own subtree"
+        );
+    }
+
+    /// Appends a new child to this node, after existing children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` or `new_child` is not a valid node of this tree,
+    /// or if they are the same node.
+    ///
+    /// In debug builds, also panics if the new child is an ancestor of this node.
+    pub fn append(&mut self, id: NodeId, new_child: NodeId) {
+        assert!(id != new_child, "a node cannot be appended to itself");
+        let (index, child_index) = (self.checked_index(id), self.checked_index(new_child));
+        self.debug_assert_not_ancestor(id, new_child);
+
+        self.detach_index(child_index);
+
+        let last_child = self.at(index).last_child;
+        {
+            let child = self.at_mut(child_index);
+            child.parent = Some(index);
+            child.previous_sibling = last_child;
+        }
+
+        match last_child {
+            Some(last_child) => self.at_mut(last_child).next_sibling = Some(child_index),
+            None => self.at_mut(index).first_child = Some(child_index),
+        }
+        self.at_mut(index).last_child = Some(child_index);
+    }
+
+    /// Prepends a new child to this node, before existing children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` or `new_child` is not a valid node of this tree,
+    /// or if they are the same node.
+    ///
+    /// In debug builds, also panics if the new child is an ancestor of this node.
+    pub fn prepend(&mut self, id: NodeId, new_child: NodeId) {
+        assert!(id != new_child, "a node cannot be prepended to itself");
+        let (index, child_index) = (self.checked_index(id), self.checked_index(new_child));
+        self.debug_assert_not_ancestor(id, new_child);
+
+        self.detach_index(child_index);
+
+        let first_child = self.at(index).first_child;
+        {
+            let child = self.at_mut(child_index);
+            child.parent = Some(index);
+            child.next_sibling = first_child;
+        }
+
+        match first_child {
+            Some(first_child) => self.at_mut(first_child).previous_sibling = Some(child_index),
+            None => self.at_mut(index).last_child = Some(child_index),
+        }
+        self.at_mut(index).first_child = Some(child_index);
+    }
+
+    /// Inserts a new sibling after this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` or `new_sibling` is not a valid node of this tree,
+    /// or if they are the same node.
+    ///
+    /// In debug builds, also panics if the new sibling is an ancestor of this node.
+    pub fn insert_after(&mut self, id: NodeId, new_sibling: NodeId) {
+        assert!(id != new_sibling, "a node cannot be inserted after itself");
+        let (index, sibling_index) = (self.checked_index(id), self.checked_index(new_sibling));
+        self.debug_assert_not_ancestor(id, new_sibling);
+
+        self.detach_index(sibling_index);
+
+        let (parent, next_sibling) = {
+            let node = self.at(index);
+            (node.parent, node.next_sibling)
+        };
+        {
+            let sibling = self.at_mut(sibling_index);
+            sibling.parent = parent;
+            sibling.previous_sibling = Some(index);
+            sibling.next_sibling = next_sibling;
+        }
+
+        match next_sibling {
+            Some(next_sibling) => self.at_mut(next_sibling).previous_sibling = Some(sibling_index),
+            None => {
+                if let Some(parent) = parent {
+                    self.at_mut(parent).last_child = Some(sibling_index);
+                }
+            }
+        }
+        self.at_mut(index).next_sibling = Some(sibling_index);
+    }
+
+    /// Inserts a new sibling before this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` or `new_sibling` is not a valid node of this tree,
+    /// or if they are the same node.
+    ///
+    /// In debug builds, also panics if the new sibling is an ancestor of this node.
+    pub fn insert_before(&mut self, id: NodeId, new_sibling: NodeId) {
+        assert!(id != new_sibling, "a node cannot be inserted before itself");
+        let (index, sibling_index) = (self.checked_index(id), self.checked_index(new_sibling));
+        self.debug_assert_not_ancestor(id, new_sibling);
+
+        self.detach_index(sibling_index);
+
+        let (parent, previous_sibling) = {
+            let node = self.at(index);
+            (node.parent, node.previous_sibling)
+        };
+        {
+            let sibling = self.at_mut(sibling_index);
+            sibling.parent = parent;
+            sibling.previous_sibling = previous_sibling;
+            sibling.next_sibling = Some(index);
+        }
+
+        match previous_sibling {
+            Some(previous_sibling) => {
+                self.at_mut(previous_sibling).next_sibling = Some(sibling_index)
+            }
+            None => {
+                if let Some(parent) = parent {
+                    self.at_mut(parent).first_child = Some(sibling_index);
+                }
+            }
+        }
+        self.at_mut(index).previous_sibling = Some(sibling_index);
+    }
+
+    /// Detaches a node and removes it and all of its descendants from the tree.
+    ///
+    /// Returns the data of the removed node. The ids of all removed nodes become invalid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn remove(&mut self, id: NodeId) -> T {
+        self.detach(id);
+
+        let removed: Vec<NodeId> = self.descendants(id).collect();
+        let mut data = None;
+        for node_id in removed {
+            let slot = &mut self.slots[node_id.index];
+            slot.generation = slot.generation.wrapping_add(1);
+            let node = slot.node.take().unwrap();
+            if node_id == id {
+                data = Some(node.data);
+            }
+
+            self.free.push(node_id.index);
+            self.len -= 1;
+        }
+
+        data.unwrap()
+    }
+
+    /// Returns a copy of a current node without children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn make_copy(&mut self, id: NodeId) -> NodeId
+    where
+        T: Clone,
+    {
+        let data = self[id].clone();
+        self.new_node(data)
+    }
+
+    /// Returns a copy of a current node with children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn make_deep_copy(&mut self, id: NodeId) -> NodeId
+    where
+        T: Clone,
+    {
+        let edges: Vec<NodeEdge> = self.traverse(id).collect();
+        let mut root = None;
+        let mut parents = Vec::new();
+        for edge in edges {
+            match edge {
+                NodeEdge::Start(node_id) => {
+                    let copy = self.make_copy(node_id);
+                    match parents.last() {
+                        Some(&parent) => self.append(parent, copy),
+                        None => root = Some(copy),
+                    }
+                    parents.push(copy);
+                }
+                NodeEdge::End(_) => {
+                    parents.pop();
+                }
+            }
+        }
+
+        root.unwrap()
+    }
+
+    /// Copies a `Node` and its descendants into a new tree.
+    ///
+    /// Returns the tree and the id of the copied root node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the descendant nodes are currently mutably borrowed.
+    pub fn from_node(node: &Node<T>) -> (Tree<T>, NodeId)
+    where
+        T: Clone,
+    {
+        let mut tree = Tree::new();
+        let mut root = None;
+        let mut parents = Vec::new();
+        for edge in node.traverse() {
+            match edge {
+                super::NodeEdge::Start(node) => {
+                    let id = tree.new_node(node.borrow().clone());
+                    match parents.last() {
+                        Some(&parent) => tree.append(parent, id),
+                        None => root = Some(id),
+                    }
+                    parents.push(id);
+                }
+                super::NodeEdge::End(_) => {
+                    parents.pop();
+                }
+            }
+        }
+
+        (tree, root.unwrap())
+    }
+
+    /// Copies a node and its descendants into a new `Node` tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn to_node(&self, id: NodeId) -> Node<T>
+    where
+        T: Clone,
+    {
+        let mut root = None;
+        let mut parents: Vec<Node<T>> = Vec::new();
+        for edge in self.traverse(id) {
+            match edge {
+                NodeEdge::Start(node_id) => {
+                    let node = Node::new(self[node_id].clone());
+                    match parents.last() {
+                        Some(parent) => parent.append(node.clone()),
+                        None => root = Some(node.clone()),
+                    }
+                    parents.push(node);
+                }
+                NodeEdge::End(_) => {
+                    parents.pop();
+                }
+            }
+        }
+
+        root.unwrap()
+    }
+
+    /// Converts a node and its descendants into a `Node` tree,
+    /// moving the data out of this tree.
+    ///
+    /// Nodes outside of the subtree are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not a valid node of this tree.
+    pub fn into_node(mut self, id: NodeId) -> Node<T> {
+        let edges: Vec<NodeEdge> = self.traverse(id).collect();
+        let mut root = None;
+        let mut parents: Vec<Node<T>> = Vec::new();
+        for edge in edges {
+            match edge {
+                NodeEdge::Start(node_id) => {
+                    let data = self.slots[node_id.index].node.take().unwrap().data;
+                    let node = Node::new(data);
+                    match parents.last() {
+                        Some(parent) => parent.append(node.clone()),
+                        None => root = Some(node.clone()),
+                    }
+                    parents.push(node);
+                }
+                NodeEdge::End(_) => {
+                    parents.pop();
+                }
+            }
+        }
+
+        root.unwrap()
+    }
+}
+
+/// An iterator of nodes to the ancestors a given node.
+pub struct Ancestors<'a, T: 'a> {
+    tree: &'a Tree<T>,
+    next: Option<NodeId>,
+}
+
+impl<'a, T> Iterator for Ancestors<'a, T> {
+    type Item = NodeId;
+
+    /// # Panics
+    ///
+    /// Panics if the starting node is not a valid node of the tree.
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next.take()?;
+        self.next = self.tree.parent(id);
+        Some(id)
+    }
+}
+
+/// An iterator of nodes to the siblings before a given node.
+pub struct PrecedingSiblings<'a, T: 'a> {
+    tree: &'a Tree<T>,
+    next: Option<NodeId>,
+}
+
+impl<'a, T> Iterator for PrecedingSiblings<'a, T> {
+    type Item = NodeId;
+
+    /// # Panics
+    ///
+    /// Panics if the starting node is not a valid node of the tree.
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next.take()?;
+        self.next = self.tree.previous_sibling(id);
+        Some(id)
+    }
+}
+
+/// An iterator of nodes to the siblings after a given node.
+pub struct FollowingSiblings<'a, T: 'a> {
+    tree: &'a Tree<T>,
+    next: Option<NodeId>,
+}
+
+impl<'a, T> Iterator for FollowingSiblings<'a, T> {
+    type Item = NodeId;
+
+    /// # Panics
+    ///
+    /// Panics if the starting node is not a valid node of the tree.
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next.take()?;
+        self.next = self.tree.next_sibling(id);
+        Some(id)
+    }
+}
+
+/// A double ended iterator of nodes to the children of a given node.
+pub struct Children<'a, T: 'a> {
+    tree: &'a Tree<T>,
+    next: Option<NodeId>,
+    next_back: Option<NodeId>,
+}
+
+impl<'a, T> Children<'a, T> {
+    // true if self.next_back's next sibling is self.next
+    fn finished(&self) -> bool {
+        match self.next_back {
+            Some(next_back) => self.tree.next_sibling(next_back) == self.next,
+            _ => true,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Children<'a, T> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let id = self.next.take()?;
+        self.next = self.tree.next_sibling(id);
+        Some(id)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Children<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let id = self.next_back.take()?;
+        self.next_back = self.tree.previous_sibling(id);
+        Some(id)
+    }
+}
+
+/// An iterator of nodes to a given node and its descendants, in tree order.
+pub struct Descendants<'a, T: 'a>(Traverse<'a, T>);
+
+impl<'a, T> Iterator for Descendants<'a, T> {
+    type Item = NodeId;
+
+    /// # Panics
+    ///
+    /// Panics if the starting node is not a valid node of the tree.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                Some(NodeEdge::Start(id)) => return Some(id),
+                Some(NodeEdge::End(_)) => {}
+                None => return None,
+            }
+        }
+    }
+}
+
+/// A node type during traverse.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeEdge {
+    /// Indicates that start of a node that has children.
+    /// Yielded by `Traverse::next` before the node's descendants.
+    /// In HTML or XML, this corresponds to an opening tag like `<div>`
+    Start(NodeId),
+
+    /// Indicates that end of a node that has children.
+    /// Yielded by `Traverse::next` after the node's descendants.
+    /// In HTML or XML, this corresponds to a closing tag like `</div>`
+    End(NodeId),
+}
+
+impl NodeEdge {
+    fn next_edge<T>(&self, tree: &Tree<T>, root: NodeId) -> Option<NodeEdge> {
+        match *self {
+            NodeEdge::Start(id) => match tree.first_child(id) {
+                Some(first_child) => Some(NodeEdge::Start(first_child)),
+                None => Some(NodeEdge::End(id)),
+            },
+            NodeEdge::End(id) => {
+                if id == root {
+                    None
+                } else {
+                    match tree.next_sibling(id) {
+                        Some(next_sibling) => Some(NodeEdge::Start(next_sibling)),
+                        None => tree.parent(id).map(NodeEdge::End),
+                    }
+                }
+            }
+        }
+    }
+
+    fn previous_edge<T>(&self, tree: &Tree<T>, root: NodeId) -> Option<NodeEdge> {
+        match *self {
+            NodeEdge::End(id) => match tree.last_child(id) {
+                Some(last_child) => Some(NodeEdge::End(last_child)),
+                None => Some(NodeEdge::Start(id)),
+            },
+            NodeEdge::Start(id) => {
+                if id == root {
+                    None
+                } else {
+                    match tree.previous_sibling(id) {
+                        Some(previous_sibling) => Some(NodeEdge::End(previous_sibling)),
+                        None => tree.parent(id).map(NodeEdge::Start),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A double ended iterator of nodes to a given node and its descendants,
+/// in tree order.
+pub struct Traverse<'a, T: 'a> {
+    tree: &'a Tree<T>,
+    root: NodeId,
+    next: Option<NodeEdge>,
+    next_back: Option<NodeEdge>,
+}
+
+impl<'a, T> Traverse<'a, T> {
+    // true if self.next_back's next edge is self.next
+    fn finished(&self) -> bool {
+        match self.next_back {
+            Some(next_back) => next_back.next_edge(self.tree, self.root) == self.next,
+            _ => true,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Traverse<'a, T> {
+    type Item = NodeEdge;
+
+    /// # Panics
+    ///
+    /// Panics if the starting node is not a valid node of the tree.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let edge = self.next.take()?;
+        self.next = edge.next_edge(self.tree, self.root);
+        Some(edge)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Traverse<'a, T> {
+    /// # Panics
+    ///
+    /// Panics if the starting node is not a valid node of the tree.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let edge = self.next_back.take()?;
+        self.next_back = edge.previous_edge(self.tree, self.root);
+        Some(edge)
+    }
+}
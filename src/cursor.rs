@@ -0,0 +1,198 @@
+use std::mem;
+
+use super::Node;
+
+/// A movable position in a tree.
+///
+/// The cursor keeps strong references to all the ancestors it came through,
+/// so it can always go back up, even if no other references to them are left.
+///
+/// Methods that move the cursor return `false` and leave it in place
+/// if there is no node to move to.
+pub struct TreeCursor<T> {
+    node: Node<T>,
+    // Strong references to the ancestors of `node`, from the topmost visited one.
+    ancestors: Vec<Node<T>>,
+}
+
+impl<T> Clone for TreeCursor<T> {
+    fn clone(&self) -> Self {
+        TreeCursor {
+            node: self.node.clone(),
+            ancestors: self.ancestors.clone(),
+        }
+    }
+}
+
+impl<T> TreeCursor<T> {
+    /// Creates a cursor positioned at a node.
+    pub fn new(node: Node<T>) -> TreeCursor<T> {
+        TreeCursor {
+            node,
+            ancestors: Vec::new(),
+        }
+    }
+
+    /// Returns the node at the cursor.
+    pub fn node(&self) -> &Node<T> {
+        &self.node
+    }
+
+    /// Returns the node at the cursor, consuming the cursor.
+    pub fn into_node(self) -> Node<T> {
+        self.node
+    }
+
+    /// Moves the cursor to the parent node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently mutably borrowed.
+    pub fn to_parent(&mut self) -> bool {
+        match self.node.parent() {
+            Some(parent) => {
+                if self.ancestors.last() == Some(&parent) {
+                    self.ancestors.pop();
+                } else {
+                    // The tree was changed outside of the cursor.
+                    self.ancestors.clear();
+                }
+
+                self.node = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the cursor to the root of the tree.
+    ///
+    /// Returns `false` if the cursor is already at the root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its ancestors is currently mutably borrowed.
+    pub fn to_root(&mut self) -> bool {
+        let mut moved = false;
+        while self.to_parent() {
+            moved = true;
+        }
+
+        moved
+    }
+
+    /// Moves the cursor to the first child.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently mutably borrowed.
+    pub fn to_first_child(&mut self) -> bool {
+        let child = self.node.first_child();
+        self.move_to_child(child)
+    }
+
+    /// Moves the cursor to the last child.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently mutably borrowed.
+    pub fn to_last_child(&mut self) -> bool {
+        let child = self.node.last_child();
+        self.move_to_child(child)
+    }
+
+    /// Moves the cursor to the next sibling.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently mutably borrowed.
+    pub fn to_next_sibling(&mut self) -> bool {
+        let sibling = self.node.next_sibling();
+        self.move_to_sibling(sibling)
+    }
+
+    /// Moves the cursor to the previous sibling.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently mutably borrowed.
+    pub fn to_previous_sibling(&mut self) -> bool {
+        let sibling = self.node.previous_sibling();
+        self.move_to_sibling(sibling)
+    }
+
+    fn move_to_child(&mut self, child: Option<Node<T>>) -> bool {
+        match child {
+            Some(child) => {
+                let parent = mem::replace(&mut self.node, child);
+                self.ancestors.push(parent);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn move_to_sibling(&mut self, sibling: Option<Node<T>>) -> bool {
+        match sibling {
+            Some(sibling) => {
+                self.node = sibling;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts a new sibling before the node at the cursor.
+    ///
+    /// The cursor stays at the same node.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::insert_before`.
+    pub fn insert_before(&self, new_sibling: Node<T>) {
+        self.node.insert_before(new_sibling);
+    }
+
+    /// Inserts a new sibling after the node at the cursor.
+    ///
+    /// The cursor stays at the same node.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::insert_after`.
+    pub fn insert_after(&self, new_sibling: Node<T>) {
+        self.node.insert_after(new_sibling);
+    }
+
+    /// Puts a new node in place of the node at the cursor and moves the cursor to it.
+    ///
+    /// Returns the replaced node, which is detached along with its children.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::insert_before` and `Node::detach`.
+    pub fn replace(&mut self, new_node: Node<T>) -> Node<T> {
+        self.node.insert_before(new_node.clone());
+        self.node.detach();
+        mem::replace(&mut self.node, new_node)
+    }
+
+    /// Detaches the node at the cursor and moves the cursor to the next sibling,
+    /// or to the parent if there is no next sibling.
+    ///
+    /// Returns the removed node, or `None` if the node has neither a next sibling nor a parent.
+    /// The tree is left unchanged in this case.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::detach`.
+    pub fn remove(&mut self) -> Option<Node<T>> {
+        let removed = self.node.clone();
+        if !self.to_next_sibling() && !self.to_parent() {
+            return None;
+        }
+
+        removed.detach();
+        Some(removed)
+    }
+}
@@ -0,0 +1,436 @@
+/*!
+Tree differencing.
+
+`diff` compares two subtrees and returns an edit script,
+which `apply` can replay on a copy of the old subtree to turn it into the new one.
+
+Children are matched per parent: first by equal subtrees, then by equal data,
+and the remaining children are paired in order.
+Matched children that changed their order are reported as moves,
+keeping the longest run of children that are already in order in place.
+Unmatched children are deleted or inserted as whole subtrees.
+Moves are only detected between siblings.
+*/
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use super::{Node, TreePath};
+
+/// A single edit operation.
+///
+/// Paths are resolved against the tree as it is when the edit is applied,
+/// i.e. after all the previous edits of the script.
+#[derive(Clone, Debug)]
+pub enum Edit<T> {
+    /// Replaces the data of the node at `path`.
+    Update {
+        /// The path to the updated node.
+        path: TreePath,
+        /// The new data.
+        data: T,
+    },
+
+    /// Inserts a copy of `node` and its descendants
+    /// as the `index`th child of the node at `parent`.
+    Insert {
+        /// The path to the new parent.
+        parent: TreePath,
+        /// The position among the new siblings.
+        index: usize,
+        /// The inserted subtree.
+        node: Node<T>,
+    },
+
+    /// Detaches the node at `path`, with its descendants.
+    Delete {
+        /// The path to the deleted node.
+        path: TreePath,
+    },
+
+    /// Moves the node at `from` to be the `index`th child of the node at `parent`.
+    ///
+    /// `parent` and `index` are resolved after the node has been detached.
+    Move {
+        /// The path to the moved node.
+        from: TreePath,
+        /// The path to the new parent.
+        parent: TreePath,
+        /// The position among the new siblings.
+        index: usize,
+    },
+}
+
+/// An error returned by `apply` when an edit refers to a node that does not exist.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ApplyError {
+    /// The position of the failed edit in the script.
+    pub edit: usize,
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "edit {} refers to a node that does not exist", self.edit)
+    }
+}
+
+impl error::Error for ApplyError {
+    fn description(&self) -> &str {
+        "an edit refers to a node that does not exist"
+    }
+}
+
+/// Returns an edit script that turns `old` and its descendants into `new` and its descendants.
+///
+/// The roots are always matched with each other.
+///
+/// # Panics
+///
+/// Panics if any of the descendant nodes are currently mutably borrowed.
+pub fn diff<T: PartialEq + Clone>(old: &Node<T>, new: &Node<T>) -> Vec<Edit<T>> {
+    let mut edits = Vec::new();
+    let mut comparison = Comparison {
+        results: HashMap::new(),
+        marker: PhantomData,
+    };
+
+    // Use an explicit stack to prevent a stack overflow on deep trees.
+    let mut open_set = vec![(old.clone(), new.clone(), TreePath::new())];
+    while let Some((old, new, path)) = open_set.pop() {
+        if *old.borrow() != *new.borrow() {
+            edits.push(Edit::Update {
+                path: path.clone(),
+                data: new.borrow().clone(),
+            });
+        }
+
+        let pairs = diff_children(&old, &new, &path, &mut comparison, &mut edits);
+
+        // Push in reverse, so children are processed in order.
+        for (old_child, new_child, index) in pairs.into_iter().rev() {
+            let mut child_path = path.clone();
+            child_path.push(index);
+            open_set.push((old_child, new_child, child_path));
+        }
+    }
+
+    edits
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Entry {
+    Old(usize),
+    New(usize),
+}
+
+struct Matching {
+    // For each new child, the index of the matched old child.
+    matches: Vec<Option<usize>>,
+    // For each new child, whether the matched subtrees are equal.
+    equal: Vec<bool>,
+    // For each old child, whether it is matched.
+    matched: Vec<bool>,
+}
+
+impl Matching {
+    fn match_by<T, F>(&mut self, old: &[Node<T>], new: &[Node<T>], is_equal: bool, mut is_match: F)
+    where
+        F: FnMut(&Node<T>, &Node<T>) -> bool,
+    {
+        // Start each search after the previous match, since most children keep their order.
+        let mut cursor = 0;
+        for (j, new_child) in new.iter().enumerate() {
+            if self.matches[j].is_some() {
+                continue;
+            }
+
+            for offset in 0..old.len() {
+                let i = (cursor + offset) % old.len();
+                if !self.matched[i] && is_match(&old[i], new_child) {
+                    self.matches[j] = Some(i);
+                    self.equal[j] = is_equal;
+                    self.matched[i] = true;
+                    cursor = i + 1;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Emits the edits that rearrange the children of `old` into the order of the children of `new`.
+//
+// Returns the matched children pairs that differ, along with their final index.
+fn diff_children<T: PartialEq + Clone>(
+    old: &Node<T>,
+    new: &Node<T>,
+    path: &TreePath,
+    comparison: &mut Comparison<T>,
+    edits: &mut Vec<Edit<T>>,
+) -> Vec<(Node<T>, Node<T>, usize)> {
+    let old_children: Vec<_> = old.children().collect();
+    let new_children: Vec<_> = new.children().collect();
+
+    let mut matching = Matching {
+        matches: vec![None; new_children.len()],
+        equal: vec![false; new_children.len()],
+        matched: vec![false; old_children.len()],
+    };
+    matching.match_by(&old_children, &new_children, true, |a, b| {
+        comparison.subtree_eq(a, b)
+    });
+    matching.match_by(&old_children, &new_children, false, |a, b| {
+        *a.borrow() == *b.borrow()
+    });
+    matching.match_by(&old_children, &new_children, false, |_, _| true);
+    let Matching {
+        matches,
+        equal,
+        matched,
+    } = matching;
+
+    // Delete unmatched old children, starting from the end to keep the indices valid.
+    for i in (0..old_children.len()).rev() {
+        if !matched[i] {
+            let mut child_path = path.clone();
+            child_path.push(i);
+            edits.push(Edit::Delete { path: child_path });
+        }
+    }
+
+    let mut current: Vec<Entry> = (0..old_children.len())
+        .filter(|&i| matched[i])
+        .map(Entry::Old)
+        .collect();
+
+    // Children in the longest increasing subsequence of old indices stay in place.
+    let old_order: Vec<usize> = matches.iter().filter_map(|m| *m).collect();
+    let mut stable = vec![false; old_children.len()];
+    for i in longest_increasing_subsequence(&old_order) {
+        stable[old_order[i]] = true;
+    }
+
+    // Place the children starting from the end,
+    // so each one can be positioned before its already placed next sibling.
+    let mut anchor = current.len();
+    for j in (0..new_children.len()).rev() {
+        match matches[j] {
+            Some(i) => {
+                let from = current.iter().position(|e| *e == Entry::Old(i)).unwrap();
+                if !stable[i] {
+                    current.remove(from);
+                    let index = if from < anchor { anchor - 1 } else { anchor };
+                    current.insert(index, Entry::Old(i));
+
+                    let mut from_path = path.clone();
+                    from_path.push(from);
+                    edits.push(Edit::Move {
+                        from: from_path,
+                        parent: path.clone(),
+                        index,
+                    });
+                    anchor = index;
+                } else {
+                    anchor = from;
+                }
+            }
+            None => {
+                current.insert(anchor, Entry::New(j));
+                edits.push(Edit::Insert {
+                    parent: path.clone(),
+                    index: anchor,
+                    node: new_children[j].make_deep_copy(),
+                });
+            }
+        }
+    }
+
+    matches
+        .iter()
+        .enumerate()
+        .filter(|&(j, _)| !equal[j])
+        .filter_map(|(j, m)| m.map(|i| (old_children[i].clone(), new_children[j].clone(), j)))
+        .collect()
+}
+
+// Returns the indices of a longest strictly increasing subsequence.
+fn longest_increasing_subsequence(values: &[usize]) -> Vec<usize> {
+    // `tails[k]` is the index of the smallest tail of an increasing subsequence of length `k + 1`.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let k = match tails.binary_search_by(|&t| values[t].cmp(&value)) {
+            Ok(k) | Err(k) => k,
+        };
+
+        if k > 0 {
+            previous[i] = Some(tails[k - 1]);
+        }
+
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut result = Vec::with_capacity(tails.len());
+    let mut i = tails.last().cloned();
+    while let Some(index) = i {
+        result.push(index);
+        i = previous[index];
+    }
+    result.reverse();
+    result
+}
+
+// Checks whether subtrees have the same shape and data.
+//
+// Results are remembered for every compared pair of nodes,
+// so nested subtrees are compared only once.
+struct Comparison<T> {
+    results: HashMap<(usize, usize), bool>,
+    marker: PhantomData<T>,
+}
+
+impl<T: PartialEq> Comparison<T> {
+    fn subtree_eq(&mut self, a: &Node<T>, b: &Node<T>) -> bool {
+        // Pairs of nodes along with their next children to compare.
+        let mut stack = Vec::new();
+        let mut result = self.enter(a, b, &mut stack);
+        loop {
+            // A result belongs to the current children of the topmost pair.
+            if let Some(eq) = result {
+                match stack.last_mut() {
+                    Some(top) => {
+                        if eq {
+                            top.2 = top.2.take().and_then(|node| node.next_sibling());
+                            top.3 = top.3.take().and_then(|node| node.next_sibling());
+                        }
+                    }
+                    None => return eq,
+                }
+
+                if !eq {
+                    let (a, b, _, _) = stack.pop().unwrap();
+                    self.results.insert(key(&a, &b), false);
+                    continue;
+                }
+            }
+
+            let children = {
+                let top = stack.last().unwrap();
+                (top.2.clone(), top.3.clone())
+            };
+
+            result = match children {
+                (Some(a_child), Some(b_child)) => self.enter(&a_child, &b_child, &mut stack),
+                _ => {
+                    // Children counts are checked on enter, so both are finished here.
+                    let (a, b, _, _) = stack.pop().unwrap();
+                    self.results.insert(key(&a, &b), true);
+                    Some(true)
+                }
+            };
+        }
+    }
+
+    // Returns the result if it is known without comparing the children.
+    fn enter(&mut self, a: &Node<T>, b: &Node<T>, stack: &mut Vec<Frame<T>>) -> Option<bool> {
+        if let Some(&eq) = self.results.get(&key(a, b)) {
+            return Some(eq);
+        }
+
+        if *a.borrow() != *b.borrow() || a.child_count() != b.child_count() {
+            self.results.insert(key(a, b), false);
+            return Some(false);
+        }
+
+        stack.push((a.clone(), b.clone(), a.first_child(), b.first_child()));
+        None
+    }
+}
+
+type Frame<T> = (Node<T>, Node<T>, Option<Node<T>>, Option<Node<T>>);
+
+// Both trees are alive during the comparison, so node addresses are unique.
+fn key<T>(a: &Node<T>, b: &Node<T>) -> (usize, usize) {
+    (&*a.0 as *const _ as usize, &*b.0 as *const _ as usize)
+}
+
+/// Applies an edit script to `root` and its descendants.
+///
+/// Stops at the first edit that refers to a node that does not exist.
+/// The edits before it stay applied.
+///
+/// # Panics
+///
+/// Panics if any of the affected nodes are currently borrowed.
+pub fn apply<T: Clone>(root: &Node<T>, edits: &[Edit<T>]) -> Result<(), ApplyError> {
+    for (i, edit) in edits.iter().enumerate() {
+        let error = ApplyError { edit: i };
+        match *edit {
+            Edit::Update { ref path, ref data } => {
+                let node = root.resolve(path).ok_or(error)?;
+                *node.borrow_mut() = data.clone();
+            }
+            Edit::Insert {
+                ref parent,
+                index,
+                ref node,
+            } => {
+                let parent = root.resolve(parent).ok_or(error)?;
+                insert_child(&parent, index, node.make_deep_copy()).ok_or(error)?;
+            }
+            Edit::Delete { ref path } => {
+                let node = root.resolve(path).ok_or(error)?;
+                if node == *root {
+                    return Err(error);
+                }
+
+                node.detach();
+            }
+            Edit::Move {
+                ref from,
+                ref parent,
+                index,
+            } => {
+                let node = root.resolve(from).ok_or(error)?;
+                if node == *root {
+                    return Err(error);
+                }
+
+                let previous_sibling = node.previous_sibling();
+                let old_parent = node.parent();
+                node.detach();
+
+                let inserted = root
+                    .resolve(parent)
+                    .and_then(|parent| insert_child(&parent, index, node.clone()));
+                if inserted.is_none() {
+                    // Put the node back.
+                    match (previous_sibling, old_parent) {
+                        (Some(previous_sibling), _) => previous_sibling.insert_after(node),
+                        (None, Some(old_parent)) => old_parent.prepend(node),
+                        (None, None) => {}
+                    }
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_child<T>(parent: &Node<T>, index: usize, node: Node<T>) -> Option<()> {
+    match parent.nth_child(index) {
+        Some(child) => child.insert_before(node),
+        None if index == parent.child_count() => parent.append(node),
+        None => return None,
+    }
+
+    Some(())
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+use super::Node;
+
+/// A `Display` adapter that draws a subtree, one node per line.
+///
+/// Created by `Node::display_tree` and `Node::display_tree_with`.
+///
+/// ```text
+/// 1
+/// ├── 2
+/// │   └── 3
+/// └── 4
+/// ```
+///
+/// Children that are not shown, because of `max_depth` or `max_children`,
+/// are replaced with a `... (N more)` line.
+pub struct DisplayTree<T, F> {
+    node: Node<T>,
+    label: F,
+    max_depth: Option<usize>,
+    max_children: Option<usize>,
+}
+
+impl<T, F> DisplayTree<T, F> {
+    /// Hides the nodes deeper than `max_depth`, where the node itself has a depth of zero.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Shows at most `max_children` children of each node.
+    pub fn max_children(mut self, max_children: usize) -> Self {
+        self.max_children = Some(max_children);
+        self
+    }
+}
+
+// The children of a node that are being drawn.
+struct Level<T> {
+    next: Option<Node<T>>,
+    shown: usize,
+}
+
+impl<T, F> fmt::Display for DisplayTree<T, F>
+where
+    F: Fn(&T) -> String,
+{
+    /// # Panics
+    ///
+    /// Panics if one of the drawn nodes is currently mutably borrowed.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&(self.label)(&self.node.borrow()))?;
+
+        // Uses an explicit stack, so that deep trees do not overflow the call stack.
+        let mut levels = Vec::new();
+        self.open(f, &mut levels, &self.node)?;
+        while let Some(next) = levels.last_mut().map(|level| level.next.take()) {
+            let child = match next {
+                Some(child) => child,
+                None => {
+                    levels.pop();
+                    continue;
+                }
+            };
+
+            let too_many = {
+                let level = levels.last_mut().unwrap();
+                level.shown += 1;
+                let too_many = self.max_children.into_iter().any(|max| level.shown > max);
+                if !too_many {
+                    level.next = child.next_sibling();
+                }
+
+                too_many
+            };
+
+            write_guides(f, &levels, true)?;
+            if too_many {
+                write!(f, "... ({} more)", child.following_siblings().count())?;
+                continue;
+            }
+
+            f.write_str(&(self.label)(&child.borrow()))?;
+            self.open(f, &mut levels, &child)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T, F> DisplayTree<T, F> {
+    // Starts drawing the children of a node that was just drawn,
+    // or writes how many there are if they are too deep.
+    fn open(
+        &self,
+        f: &mut fmt::Formatter,
+        levels: &mut Vec<Level<T>>,
+        node: &Node<T>,
+    ) -> fmt::Result {
+        if !node.has_children() {
+            return Ok(());
+        }
+
+        if self.max_depth.into_iter().any(|max| levels.len() >= max) {
+            write_guides(f, levels, false)?;
+            write!(f, "{}... ({} more)", LAST, node.child_count())?;
+            return Ok(());
+        }
+
+        levels.push(Level {
+            next: node.first_child(),
+            shown: 0,
+        });
+
+        Ok(())
+    }
+}
+
+const LAST: &str = "└── ";
+
+// Starts a new line with the guides of all the levels.
+//
+// With `connect`, the last level gets a connector to the node on this line.
+fn write_guides<T>(f: &mut fmt::Formatter, levels: &[Level<T>], connect: bool) -> fmt::Result {
+    f.write_str("\n")?;
+    for (i, level) in levels.iter().enumerate() {
+        let guide = match (connect && i + 1 == levels.len(), level.next.is_some()) {
+            (true, true) => "├── ",
+            (true, false) => LAST,
+            (false, true) => "│   ",
+            (false, false) => "    ",
+        };
+
+        f.write_str(guide)?;
+    }
+
+    Ok(())
+}
+
+impl<T: fmt::Debug> Node<T> {
+    /// Returns a `Display` adapter that draws this node and its descendants,
+    /// labeled with their `Debug` representation.
+    ///
+    /// `{:#?}` draws a node the same way.
+    pub fn display_tree(&self) -> DisplayTree<T, fn(&T) -> String> {
+        self.display_tree_with(debug_label::<T>)
+    }
+}
+
+impl<T> Node<T> {
+    /// Returns a `Display` adapter that draws this node and its descendants,
+    /// labeled by `label`.
+    pub fn display_tree_with<F>(&self, label: F) -> DisplayTree<T, F>
+    where
+        F: Fn(&T) -> String,
+    {
+        DisplayTree {
+            node: self.clone(),
+            label,
+            max_depth: None,
+            max_children: None,
+        }
+    }
+}
+
+fn debug_label<T: fmt::Debug>(data: &T) -> String {
+    format!("{:?}", data)
+}
@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::Node;
+
+/// A `Display` adapter that writes a subtree as a Graphviz digraph.
+///
+/// Created by `Node::dot`. Edges go from parents to children, in sibling order.
+/// Links to nodes outside of the subtree are not drawn.
+pub struct Dot<T, F> {
+    node: Node<T>,
+    label: F,
+    back_links: bool,
+    highlighted: Vec<Node<T>>,
+}
+
+impl<T, F> Dot<T, F> {
+    /// Also draws the weak `previous_sibling` and `last_child` links as dashed edges.
+    ///
+    /// Useful for spotting links that disagree with the parent and sibling ones.
+    pub fn back_links(mut self, show: bool) -> Self {
+        self.back_links = show;
+        self
+    }
+
+    /// Fills the given nodes with a color.
+    pub fn highlight<'a, I>(mut self, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Node<T>>,
+        T: 'a,
+    {
+        self.highlighted.extend(nodes.into_iter().cloned());
+        self
+    }
+}
+
+impl<T, F> fmt::Display for Dot<T, F>
+where
+    F: Fn(&T) -> String,
+{
+    /// # Panics
+    ///
+    /// Panics if one of the written nodes is currently mutably borrowed.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Nodes are named by their position in tree order.
+        let nodes: Vec<Node<T>> = self.node.descendants().collect();
+        let ids: HashMap<usize, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.address(), i))
+            .collect();
+        let highlighted: HashSet<usize> = self.highlighted.iter().map(Node::address).collect();
+
+        f.write_str("digraph {\n")?;
+        f.write_str("    ordering=out;\n")?;
+        for (i, node) in nodes.iter().enumerate() {
+            write!(f, "    n{} [label=\"", i)?;
+            write_escaped(f, &(self.label)(&node.borrow()))?;
+            f.write_str("\"")?;
+            if highlighted.contains(&node.address()) {
+                f.write_str(", style=filled, fillcolor=yellow")?;
+            }
+
+            f.write_str("];\n")?;
+        }
+
+        for (i, node) in nodes.iter().enumerate() {
+            for child in node.children() {
+                writeln!(f, "    n{} -> n{};", i, ids[&child.address()])?;
+            }
+        }
+
+        if self.back_links {
+            for (i, node) in nodes.iter().enumerate() {
+                let links = [
+                    (node.previous_sibling(), "previous_sibling"),
+                    (node.last_child(), "last_child"),
+                ];
+
+                for &(ref link, name) in &links {
+                    if let Some(target) = link.as_ref().and_then(|link| ids.get(&link.address())) {
+                        writeln!(
+                            f,
+                            "    n{} -> n{} [style=dashed, constraint=false, label=\"{}\"];",
+                            i, target, name
+                        )?;
+                    }
+                }
+            }
+        }
+
+        f.write_str("}\n")
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter, label: &str) -> fmt::Result {
+    for c in label.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            _ => write!(f, "{}", c)?,
+        }
+    }
+
+    Ok(())
+}
+
+impl<T> Node<T> {
+    /// Returns a `Display` adapter that writes this node and its descendants
+    /// as a Graphviz digraph, labeled by `label`.
+    ///
+    /// Can be written to any `io::Write` with `write!`.
+    pub fn dot<F>(&self, label: F) -> Dot<T, F>
+    where
+        F: Fn(&T) -> String,
+    {
+        Dot {
+            node: self.clone(),
+            label,
+            back_links: false,
+            highlighted: Vec::new(),
+        }
+    }
+
+    /// Returns this node and its descendants as a Graphviz digraph, labeled by `label`.
+    ///
+    /// Same as `dot(label).to_string()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its descendants is currently mutably borrowed.
+    pub fn to_dot<F>(&self, label: F) -> String
+    where
+        F: Fn(&T) -> String,
+    {
+        self.dot(label).to_string()
+    }
+}
@@ -35,6 +35,7 @@ However, if for example the only reference that exists from outside the tree
 is one that you use to traverse it,
 you will not be able to go back "up" the tree to ancestors and previous siblings after going "down",
 as those nodes will have been destroyed.
+A [`TreeCursor`](struct.TreeCursor.html) keeps the nodes it came through alive for that purpose.
 
 Weak references to destroyed nodes are treated as if they were not set at all.
 (E.g. a node can become a root when its parent is destroyed.)
@@ -50,10 +51,23 @@ Advantages:
 Disadvantages:
 
 * The tree can only be accessed from the thread is was created in.
+  The [`sync`](sync/index.html) module provides a thread-safe alternative.
 * Any tree manipulation, including read-only traversals,
   requires incrementing and decrementing reference counts,
   which causes run-time overhead.
 * Nodes are allocated individually, which may cause memory fragmentation and hurt performance.
+  The [`arena`](arena/index.html) module provides an arena-backed alternative.
+
+The [`diff`](diff/index.html) module computes and applies edit scripts between two subtrees.
+Changes made to a tree can be collected with a [`MutationObserver`](struct.MutationObserver.html).
+Mutations made through a [`Transaction`](struct.Transaction.html) can be rolled back.
+The [`xpath`](xpath/index.html) module selects nodes with XPath location paths,
+and the [`selectors`](selectors/index.html) module with CSS selectors.
+For debugging, a subtree can be drawn as text with `Node::display_tree`
+or exported to Graphviz with `Node::to_dot`.
+
+With the `serde` feature enabled, `Node<T>` implements `Serialize` and `Deserialize`
+for a whole subtree, represented as `{ "data": T, "children": [...] }`.
 
 */
 
@@ -61,12 +75,154 @@ Disadvantages:
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
+#[cfg(feature = "serde")]
+extern crate serde;
+
 use std::cell::{Ref, RefCell, RefMut};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::error;
 use std::fmt;
+use std::iter::Rev;
 use std::rc::{Rc, Weak};
 
+pub mod arena;
+mod cursor;
+pub mod diff;
+mod display;
+mod dot;
+mod observer;
+mod order;
+mod path;
+pub mod selectors;
+#[cfg(feature = "serde")]
+mod serde_impls;
+pub mod sync;
+mod transaction;
+mod visitor;
+pub mod xpath;
+
+pub use cursor::TreeCursor;
+pub use display::DisplayTree;
+pub use dot::Dot;
+pub use observer::{MutationObserver, MutationRecord};
+pub use order::DocumentPosition;
+pub use path::{ParsePathError, TreePath};
+pub use transaction::{Savepoint, Transaction};
+pub use visitor::{VisitResult, Visitor};
+
 type Link<T> = Rc<RefCell<NodeData<T>>>;
 type WeakLink<T> = Weak<RefCell<NodeData<T>>>;
+type Neighbor<T> = fn(&Node<T>) -> Option<Node<T>>;
+
+/// An error returned by the `try_*` methods.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TreeError {
+    /// A node could not be borrowed, because it is already borrowed.
+    AlreadyBorrowed {
+        /// The node that is already borrowed.
+        node: BorrowedNode,
+        /// The kind of the existing borrow.
+        kind: BorrowKind,
+    },
+
+    /// A node cannot be inserted relative to itself.
+    InsertSelf,
+
+    /// A node cannot be inserted into its own subtree,
+    /// since that would create a reference cycle.
+    InsertAncestor,
+}
+
+/// A node that caused `TreeError::AlreadyBorrowed`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BorrowedNode {
+    /// The node the method was called on.
+    ///
+    /// For iterators, this is the node about to be yielded.
+    This,
+
+    /// The node passed to the method, like a new child or a new sibling.
+    Other,
+
+    /// A parent, sibling or child of one of the above, which links had to be updated.
+    Adjacent,
+}
+
+/// A kind of an existing `RefCell` borrow.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BorrowKind {
+    /// The node is borrowed via `Node::borrow`.
+    Shared,
+
+    /// The node is borrowed via `Node::borrow_mut`.
+    Mutable,
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TreeError::AlreadyBorrowed { node, kind } => {
+                let node = match node {
+                    BorrowedNode::This => "the node",
+                    BorrowedNode::Other => "the other node",
+                    BorrowedNode::Adjacent => "an adjoining node",
+                };
+
+                let kind = match kind {
+                    BorrowKind::Shared => "borrowed",
+                    BorrowKind::Mutable => "mutably borrowed",
+                };
+
+                write!(f, "{} is already {}", node, kind)
+            }
+            TreeError::InsertSelf => write!(f, "a node cannot be inserted relative to itself"),
+            TreeError::InsertAncestor => {
+                write!(f, "a node cannot be inserted into its own subtree")
+            }
+        }
+    }
+}
+
+impl error::Error for TreeError {
+    fn description(&self) -> &str {
+        match *self {
+            TreeError::AlreadyBorrowed { .. } => "a node is already borrowed",
+            TreeError::InsertSelf => "a node cannot be inserted relative to itself",
+            TreeError::InsertAncestor => "a node cannot be inserted into its own subtree",
+        }
+    }
+}
+
+fn try_borrow_link<'a, T>(
+    link: &'a Link<T>,
+    node: BorrowedNode,
+) -> Result<Ref<'a, NodeData<T>>, TreeError> {
+    // A shared borrow can only fail because of a mutable one.
+    link.try_borrow().map_err(|_| TreeError::AlreadyBorrowed {
+        node,
+        kind: BorrowKind::Mutable,
+    })
+}
+
+fn try_borrow_link_mut<'a, T>(
+    link: &'a Link<T>,
+    node: BorrowedNode,
+) -> Result<RefMut<'a, NodeData<T>>, TreeError> {
+    link.try_borrow_mut().map_err(|_| {
+        let kind = if link.try_borrow().is_ok() {
+            BorrowKind::Shared
+        } else {
+            BorrowKind::Mutable
+        };
+
+        TreeError::AlreadyBorrowed { node, kind }
+    })
+}
+
+fn check_unborrowed<T>(link: &Link<T>, node: BorrowedNode) -> Result<(), TreeError> {
+    try_borrow_link_mut(link, node).map(|_| ())
+}
 
 /// A reference to a node holding a value of type `T`. Nodes form a tree.
 ///
@@ -85,6 +241,8 @@ struct NodeData<T> {
     last_child: Option<WeakLink<T>>,
     previous_sibling: Option<WeakLink<T>>,
     next_sibling: Option<Link<T>>,
+    // Boxed, since most nodes are never observed.
+    observers: Option<Box<[observer::Registration<T>]>>,
     data: T,
 }
 
@@ -101,9 +259,14 @@ impl<T> PartialEq for Node<T> {
     }
 }
 
+/// Formats the data of the node, or with `{:#?}`, draws the whole subtree like `display_tree`.
 impl<T: fmt::Debug> fmt::Debug for Node<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Debug::fmt(&*self.borrow(), f)
+        if f.alternate() {
+            fmt::Display::fmt(&self.display_tree(), f)
+        } else {
+            fmt::Debug::fmt(&*self.borrow(), f)
+        }
     }
 }
 
@@ -122,6 +285,7 @@ impl<T> Node<T> {
             last_child: None,
             previous_sibling: None,
             next_sibling: None,
+            observers: None,
             data,
         })))
     }
@@ -140,6 +304,20 @@ impl<T> Node<T> {
         Some(Node(self.0.borrow().parent.as_ref()?.upgrade()?))
     }
 
+    /// Like `parent`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently mutably borrowed.
+    pub fn try_parent(&self) -> Result<Option<Node<T>>, TreeError> {
+        let data = try_borrow_link(&self.0, BorrowedNode::This)?;
+        Ok(data
+            .parent
+            .as_ref()
+            .and_then(|weak| weak.upgrade())
+            .map(Node))
+    }
+
     /// Returns a first child of this node, unless it has no child.
     ///
     /// # Panics
@@ -149,6 +327,16 @@ impl<T> Node<T> {
         Some(Node(self.0.borrow().first_child.as_ref()?.clone()))
     }
 
+    /// Like `first_child`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently mutably borrowed.
+    pub fn try_first_child(&self) -> Result<Option<Node<T>>, TreeError> {
+        let data = try_borrow_link(&self.0, BorrowedNode::This)?;
+        Ok(data.first_child.clone().map(Node))
+    }
+
     /// Returns a last child of this node, unless it has no child.
     ///
     /// # Panics
@@ -158,6 +346,20 @@ impl<T> Node<T> {
         Some(Node(self.0.borrow().last_child.as_ref()?.upgrade()?))
     }
 
+    /// Like `last_child`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently mutably borrowed.
+    pub fn try_last_child(&self) -> Result<Option<Node<T>>, TreeError> {
+        let data = try_borrow_link(&self.0, BorrowedNode::This)?;
+        Ok(data
+            .last_child
+            .as_ref()
+            .and_then(|weak| weak.upgrade())
+            .map(Node))
+    }
+
     /// Returns the previous sibling of this node, unless it is a first child.
     ///
     /// # Panics
@@ -167,6 +369,20 @@ impl<T> Node<T> {
         Some(Node(self.0.borrow().previous_sibling.as_ref()?.upgrade()?))
     }
 
+    /// Like `previous_sibling`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently mutably borrowed.
+    pub fn try_previous_sibling(&self) -> Result<Option<Node<T>>, TreeError> {
+        let data = try_borrow_link(&self.0, BorrowedNode::This)?;
+        Ok(data
+            .previous_sibling
+            .as_ref()
+            .and_then(|weak| weak.upgrade())
+            .map(Node))
+    }
+
     /// Returns the next sibling of this node, unless it is a last child.
     ///
     /// # Panics
@@ -176,24 +392,54 @@ impl<T> Node<T> {
         Some(Node(self.0.borrow().next_sibling.as_ref()?.clone()))
     }
 
+    /// Like `next_sibling`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently mutably borrowed.
+    pub fn try_next_sibling(&self) -> Result<Option<Node<T>>, TreeError> {
+        let data = try_borrow_link(&self.0, BorrowedNode::This)?;
+        Ok(data.next_sibling.clone().map(Node))
+    }
+
     /// Returns a shared reference to this node's data.
     ///
     /// # Panics
     ///
     /// Panics if the node is currently mutably borrowed.
-    pub fn borrow(&self) -> Ref<T> {
+    pub fn borrow<'a>(&'a self) -> Ref<'a, T> {
         Ref::map(self.0.borrow(), |v| &v.data)
     }
 
+    /// Returns a shared reference to this node's data.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently mutably borrowed.
+    pub fn try_borrow<'a>(&'a self) -> Result<Ref<'a, T>, TreeError> {
+        let data = try_borrow_link(&self.0, BorrowedNode::This)?;
+        Ok(Ref::map(data, |v| &v.data))
+    }
+
     /// Returns a unique/mutable reference to this node's data.
     ///
     /// # Panics
     ///
     /// Panics if the node is currently borrowed.
-    pub fn borrow_mut(&self) -> RefMut<T> {
+    pub fn borrow_mut<'a>(&'a self) -> RefMut<'a, T> {
         RefMut::map(self.0.borrow_mut(), |v| &mut v.data)
     }
 
+    /// Returns a unique/mutable reference to this node's data.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently borrowed.
+    pub fn try_borrow_mut<'a>(&'a self) -> Result<RefMut<'a, T>, TreeError> {
+        let data = try_borrow_link_mut(&self.0, BorrowedNode::This)?;
+        Ok(RefMut::map(data, |v| &mut v.data))
+    }
+
     /// Returns an iterator of nodes to this node and its ancestors.
     ///
     /// Includes the current node.
@@ -215,6 +461,89 @@ impl<T> Node<T> {
         FollowingSiblings(Some(self.clone()))
     }
 
+    /// Returns an iterator of nodes to the nodes after this node in tree order,
+    /// excluding its descendants.
+    ///
+    /// Does not include the current node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its ancestors is currently mutably borrowed.
+    pub fn following(&self) -> Following<T> {
+        Following(self.next_after_subtree(None))
+    }
+
+    /// Returns an iterator of nodes to the nodes before this node in reverse tree order,
+    /// excluding its ancestors.
+    ///
+    /// Does not include the current node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently mutably borrowed.
+    pub fn preceding(&self) -> Preceding<T> {
+        Preceding {
+            node: Some(self.clone()),
+            ancestor: self.parent(),
+        }
+    }
+
+    /// Returns the node after this one in tree order, without leaving the subtree of `within`.
+    ///
+    /// If this node is not a descendant of `within`, the whole tree is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its ancestors is currently mutably borrowed.
+    pub fn next_in_tree_order(&self, within: &Node<T>) -> Option<Node<T>> {
+        self.first_child()
+            .or_else(|| self.next_after_subtree(Some(within)))
+    }
+
+    /// Returns the node before this one in tree order, without leaving the subtree of `within`.
+    ///
+    /// If this node is not a descendant of `within`, the whole tree is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of the nodes about to be visited is currently mutably borrowed.
+    pub fn previous_in_tree_order(&self, within: &Node<T>) -> Option<Node<T>> {
+        if self == within {
+            return None;
+        }
+
+        match self.previous_sibling() {
+            Some(sibling) => Some(sibling.last_descendant()),
+            None => self.parent(),
+        }
+    }
+
+    // Returns the first node after the subtree of this node in tree order.
+    fn next_after_subtree(&self, within: Option<&Node<T>>) -> Option<Node<T>> {
+        let mut node = self.clone();
+        loop {
+            if Some(&node) == within {
+                return None;
+            }
+
+            if let Some(sibling) = node.next_sibling() {
+                return Some(sibling);
+            }
+
+            node = node.parent()?;
+        }
+    }
+
+    // Returns the last node of the subtree of this node in tree order.
+    fn last_descendant(&self) -> Node<T> {
+        let mut node = self.clone();
+        while let Some(child) = node.last_child() {
+            node = child;
+        }
+
+        node
+    }
+
     /// Returns an iterator of nodes to this node's children.
     ///
     /// # Panics
@@ -236,166 +565,654 @@ impl<T> Node<T> {
         self.first_child().is_some()
     }
 
-    /// Returns an iterator of nodes to this node and its descendants, in tree order.
+    /// Returns the number of children of this node.
     ///
-    /// Includes the current node.
-    pub fn descendants(&self) -> Descendants<T> {
-        Descendants(self.traverse())
+    /// # Panics
+    ///
+    /// Panics if the node or one of its children is currently mutably borrowed.
+    pub fn child_count(&self) -> usize {
+        self.children().count()
     }
 
-    /// Returns an iterator of nodes to this node and its descendants, in tree order.
-    pub fn traverse(&self) -> Traverse<T> {
-        Traverse {
-            root: self.clone(),
-            next: Some(NodeEdge::Start(self.clone())),
-            next_back: Some(NodeEdge::End(self.clone())),
-        }
+    /// Returns the `n`th child of this node, counting from zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its first `n` children is currently mutably borrowed.
+    pub fn nth_child(&self, n: usize) -> Option<Node<T>> {
+        self.children().nth(n)
     }
 
-    /// Detaches a node from its parent and siblings. Children are not affected.
+    /// Returns the position of this node among its siblings, counting from zero.
     ///
     /// # Panics
     ///
-    /// Panics if the node or one of its adjoining nodes is currently borrowed.
-    pub fn detach(&self) {
-        self.0.borrow_mut().detach();
+    /// Panics if the node or one of the siblings before it is currently mutably borrowed.
+    pub fn index(&self) -> usize {
+        self.preceding_siblings().count() - 1
     }
 
-    /// Appends a new child to this node, after existing children.
+    /// Returns the number of ancestors of this node. The depth of a root is zero.
     ///
     /// # Panics
     ///
-    /// Panics if the node, the new child, or one of their adjoining nodes is currently borrowed.
-    pub fn append(&self, new_child: Node<T>) {
-        assert!(*self != new_child, "a node cannot be appended to itself");
-
-        let mut self_borrow = self.0.borrow_mut();
-        let mut last_child_opt = None;
-        {
-            let mut new_child_borrow = new_child.0.borrow_mut();
-            new_child_borrow.detach();
-            new_child_borrow.parent = Some(Rc::downgrade(&self.0));
-            if let Some(last_child_weak) = self_borrow.last_child.take() {
-                if let Some(last_child_strong) = last_child_weak.upgrade() {
-                    new_child_borrow.previous_sibling = Some(last_child_weak);
-                    last_child_opt = Some(last_child_strong);
-                }
-            }
-            self_borrow.last_child = Some(Rc::downgrade(&new_child.0));
-        }
+    /// Panics if the node or one of its ancestors is currently mutably borrowed.
+    pub fn depth(&self) -> usize {
+        self.ancestors().count() - 1
+    }
 
-        if let Some(last_child_strong) = last_child_opt {
-            let mut last_child_borrow = last_child_strong.borrow_mut();
-            debug_assert!(last_child_borrow.next_sibling.is_none());
-            last_child_borrow.next_sibling = Some(new_child.0);
-        } else {
-            // No last child
-            debug_assert!(self_borrow.first_child.is_none());
-            self_borrow.first_child = Some(new_child.0);
-        }
+    /// Returns the root of the tree this node belongs to, which can be the node itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its ancestors is currently mutably borrowed.
+    pub fn root(&self) -> Node<T> {
+        self.ancestors().last().unwrap()
     }
 
-    /// Prepends a new child to this node, before existing children.
+    /// Returns `true` if `other` is this node or one of its descendants.
+    ///
+    /// Walks up from `other`, so it is as fast as `other` is shallow.
     ///
     /// # Panics
     ///
-    /// Panics if the node, the new child, or one of their adjoining nodes is currently borrowed.
-    pub fn prepend(&self, new_child: Node<T>) {
-        assert!(*self != new_child, "a node cannot be prepended to itself");
+    /// Panics if `other` or one of its ancestors is currently mutably borrowed.
+    pub fn contains(&self, other: &Node<T>) -> bool {
+        other.ancestors().any(|ancestor| ancestor == *self)
+    }
 
-        let mut self_borrow = self.0.borrow_mut();
-        {
-            let mut new_child_borrow = new_child.0.borrow_mut();
-            new_child_borrow.detach();
-            new_child_borrow.parent = Some(Rc::downgrade(&self.0));
-            match self_borrow.first_child.take() {
-                Some(first_child_strong) => {
-                    {
-                        let mut first_child_borrow = first_child_strong.borrow_mut();
-                        debug_assert!(first_child_borrow.previous_sibling.is_none());
-                        first_child_borrow.previous_sibling = Some(Rc::downgrade(&new_child.0));
-                    }
-                    new_child_borrow.next_sibling = Some(first_child_strong);
-                }
-                None => {
-                    debug_assert!(self_borrow.first_child.is_none());
-                    self_borrow.last_child = Some(Rc::downgrade(&new_child.0));
-                }
-            }
-        }
-        self_borrow.first_child = Some(new_child.0);
+    /// Returns `true` if this node is an ancestor of `other`, excluding `other` itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` or one of its ancestors is currently mutably borrowed.
+    pub fn is_ancestor_of(&self, other: &Node<T>) -> bool {
+        self != other && self.contains(other)
     }
 
-    /// Inserts a new sibling after this node.
+    /// Returns `true` if this node is a descendant of `other`, excluding `other` itself.
     ///
     /// # Panics
     ///
-    /// Panics if the node, the new sibling, or one of their adjoining nodes is currently borrowed.
-    pub fn insert_after(&self, new_sibling: Node<T>) {
-        assert!(
-            *self != new_sibling,
-            "a node cannot be inserted after itself"
-        );
+    /// Panics if the node or one of its ancestors is currently mutably borrowed.
+    pub fn is_descendant_of(&self, other: &Node<T>) -> bool {
+        other.is_ancestor_of(self)
+    }
 
-        let mut self_borrow = self.0.borrow_mut();
-        {
-            let mut new_sibling_borrow = new_sibling.0.borrow_mut();
-            new_sibling_borrow.detach();
-            new_sibling_borrow.parent = self_borrow.parent.clone();
-            new_sibling_borrow.previous_sibling = Some(Rc::downgrade(&self.0));
-            match self_borrow.next_sibling.take() {
-                Some(next_sibling_strong) => {
-                    {
-                        let mut next_sibling_borrow = next_sibling_strong.borrow_mut();
-                        debug_assert!({
-                            let weak = next_sibling_borrow.previous_sibling.as_ref().unwrap();
-                            Rc::ptr_eq(&weak.upgrade().unwrap(), &self.0)
-                        });
-                        next_sibling_borrow.previous_sibling = Some(Rc::downgrade(&new_sibling.0));
-                    }
-                    new_sibling_borrow.next_sibling = Some(next_sibling_strong);
-                }
-                None => {
-                    if let Some(parent_ref) = self_borrow.parent.as_ref() {
-                        if let Some(parent_strong) = parent_ref.upgrade() {
-                            let mut parent_borrow = parent_strong.borrow_mut();
-                            parent_borrow.last_child = Some(Rc::downgrade(&new_sibling.0));
-                        }
+    /// Returns the lowest common ancestor of this node and `other`.
+    ///
+    /// A node is its own ancestor here, so if one node contains the other, it is returned.
+    /// Returns `None` if the nodes are in different trees.
+    ///
+    /// Visits only the ancestors of both nodes and allocates nothing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the nodes or their ancestors is currently mutably borrowed.
+    pub fn common_ancestor(&self, other: &Node<T>) -> Option<Node<T>> {
+        let depth = self.depth();
+        let other_depth = other.depth();
+        let mut ancestors = self.ancestors().skip(depth.saturating_sub(other_depth));
+        let mut other_ancestors = other.ancestors().skip(other_depth.saturating_sub(depth));
+        loop {
+            match (ancestors.next(), other_ancestors.next()) {
+                (Some(node), Some(other_node)) => {
+                    if node == other_node {
+                        return Some(node);
                     }
                 }
+                _ => return None,
             }
         }
-        self_borrow.next_sibling = Some(new_sibling.0);
     }
 
-    /// Inserts a new sibling before this node.
+    /// Returns the lowest common ancestor of all the nodes.
+    ///
+    /// Returns `None` if there are no nodes or they are in different trees.
+    ///
+    /// Each ancestor is visited at most once, however many nodes share it.
     ///
     /// # Panics
     ///
-    /// Panics if the node, the new sibling, or one of their adjoining nodes is currently borrowed.
-    pub fn insert_before(&self, new_sibling: Node<T>) {
-        assert!(
-            *self != new_sibling,
-            "a node cannot be inserted before itself"
-        );
-
-        let mut self_borrow = self.0.borrow_mut();
-        let mut previous_sibling_opt = None;
-        {
-            let mut new_sibling_borrow = new_sibling.0.borrow_mut();
-            new_sibling_borrow.detach();
-            new_sibling_borrow.parent = self_borrow.parent.clone();
-            new_sibling_borrow.next_sibling = Some(self.0.clone());
-            if let Some(previous_sibling_weak) = self_borrow.previous_sibling.take() {
-                if let Some(previous_sibling_strong) = previous_sibling_weak.upgrade() {
-                    new_sibling_borrow.previous_sibling = Some(previous_sibling_weak);
-                    previous_sibling_opt = Some(previous_sibling_strong);
+    /// Panics if one of the nodes or their ancestors is currently mutably borrowed.
+    pub fn common_ancestor_of<'a, I>(nodes: I) -> Option<Node<T>>
+    where
+        I: IntoIterator<Item = &'a Node<T>>,
+        T: 'a,
+    {
+        let mut nodes = nodes.into_iter();
+        let first = nodes.next()?;
+
+        // The ancestors of the first node, starting from the root.
+        let mut chain: Vec<Node<T>> = first.ancestors().collect();
+        chain.reverse();
+
+        // Maps the address of every visited node to the index in `chain`
+        // where its ancestors join it. The nodes are alive until this returns.
+        let mut junctions: HashMap<usize, usize> = chain
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.address(), i))
+            .collect();
+
+        let mut lowest = chain.len() - 1;
+        for node in nodes {
+            let mut path = Vec::new();
+            let mut junction = None;
+            for ancestor in node.ancestors() {
+                if let Some(&i) = junctions.get(&ancestor.address()) {
+                    junction = Some(i);
+                    break;
                 }
+
+                path.push(ancestor);
+            }
+
+            let junction = junction?;
+            lowest = lowest.min(junction);
+            for visited in path {
+                junctions.insert(visited.address(), junction);
             }
-            self_borrow.previous_sibling = Some(Rc::downgrade(&new_sibling.0));
         }
 
-        if let Some(previous_sibling_strong) = previous_sibling_opt {
+        chain.truncate(lowest + 1);
+        chain.pop()
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order.
+    ///
+    /// Includes the current node.
+    pub fn descendants(&self) -> Descendants<T> {
+        Descendants(self.traverse())
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in reverse tree order.
+    ///
+    /// Same as `descendants().rev()`.
+    pub fn reverse_descendants(&self) -> Rev<Descendants<T>> {
+        self.descendants().rev()
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants,
+    /// where each node comes after its descendants.
+    ///
+    /// Includes the current node, which comes last.
+    pub fn post_order(&self) -> PostOrder<T> {
+        PostOrder(self.traverse())
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, level by level.
+    ///
+    /// Includes the current node.
+    pub fn breadth_first(&self) -> BreadthFirst<T> {
+        BreadthFirst {
+            root: Some(self.clone()),
+            queue: VecDeque::new(),
+            parent: None,
+            next: None,
+        }
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, grouped by depth.
+    ///
+    /// The first level contains only the current node.
+    pub fn levels(&self) -> Levels<T> {
+        Levels {
+            nodes: self.breadth_first(),
+            pending: None,
+        }
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order.
+    pub fn traverse(&self) -> Traverse<T> {
+        Traverse {
+            root: self.clone(),
+            next: Some(NodeEdge::Start(self.clone())),
+            next_back: Some(NodeEdge::End(self.clone())),
+        }
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order,
+    /// which calls `filter` on each `NodeEdge::Start` to decide how to continue.
+    ///
+    /// With `Visit::SkipChildren`, the `NodeEdge::End` of the node immediately follows its start.
+    /// With `Visit::Stop`, the iteration ends without yielding the node.
+    pub fn traverse_filtered<F>(&self, filter: F) -> TraverseFiltered<T, F>
+    where
+        F: FnMut(&Node<T>) -> Visit,
+    {
+        TraverseFiltered {
+            traverse: self.traverse(),
+            filter,
+            stopped: false,
+        }
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order,
+    /// along with their depth relative to this node.
+    pub fn traverse_with_depth(&self) -> TraverseWithDepth<T> {
+        TraverseWithDepth {
+            traverse: self.traverse(),
+            depth: 0,
+            max_depth: None,
+        }
+    }
+
+    /// Like `ancestors`, but yields an error instead of panicking.
+    pub fn try_ancestors(&self) -> TryAncestors<T> {
+        TryAncestors(Some(self.clone()))
+    }
+
+    /// Like `preceding_siblings`, but yields an error instead of panicking.
+    pub fn try_preceding_siblings(&self) -> TryPrecedingSiblings<T> {
+        TryPrecedingSiblings(Some(self.clone()))
+    }
+
+    /// Like `following_siblings`, but yields an error instead of panicking.
+    pub fn try_following_siblings(&self) -> TryFollowingSiblings<T> {
+        TryFollowingSiblings(Some(self.clone()))
+    }
+
+    /// Like `children`, but yields an error instead of panicking.
+    ///
+    /// If this node is currently mutably borrowed, the iterator yields a single error.
+    pub fn try_children(&self) -> TryChildren<T> {
+        let first_and_last = self
+            .try_first_child()
+            .and_then(|first| Ok((first, self.try_last_child()?)));
+
+        match first_and_last {
+            Ok((next, next_back)) => TryChildren {
+                next,
+                next_back,
+                error: None,
+            },
+            Err(e) => TryChildren {
+                next: None,
+                next_back: None,
+                error: Some(e),
+            },
+        }
+    }
+
+    /// Like `descendants`, but yields an error instead of panicking.
+    pub fn try_descendants(&self) -> TryDescendants<T> {
+        TryDescendants(self.try_traverse())
+    }
+
+    /// Like `traverse`, but yields an error instead of panicking.
+    pub fn try_traverse(&self) -> TryTraverse<T> {
+        TryTraverse {
+            root: self.clone(),
+            next: Some(NodeEdge::Start(self.clone())),
+            next_back: Some(NodeEdge::End(self.clone())),
+        }
+    }
+
+    /// Detaches a node from its parent and siblings. Children are not affected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its adjoining nodes is currently borrowed.
+    pub fn detach(&self) {
+        let observed = if observer::is_active() {
+            self.parent()
+                .map(|parent| (parent, self.previous_sibling(), self.next_sibling()))
+        } else {
+            None
+        };
+
+        self.0.borrow_mut().detach();
+
+        if let Some((parent, previous_sibling, next_sibling)) = observed {
+            let record = MutationRecord::ChildListChanged {
+                parent: parent.clone(),
+                added: Vec::new(),
+                removed: vec![self.clone()],
+                previous_sibling,
+                next_sibling,
+            };
+            observer::queue(&parent, record);
+        }
+    }
+
+    /// Like `detach`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node or one of its adjoining nodes
+    /// is currently borrowed. The tree is left unchanged in this case.
+    pub fn try_detach(&self) -> Result<(), TreeError> {
+        self.check_detach(BorrowedNode::This)?;
+        self.detach();
+        Ok(())
+    }
+
+    // Checks that a node and all the nodes that `detach` will update can be borrowed.
+    fn check_detach(&self, node: BorrowedNode) -> Result<(), TreeError> {
+        let (parent, previous_sibling, next_sibling) = {
+            let data = try_borrow_link_mut(&self.0, node)?;
+            (
+                data.parent.as_ref().and_then(|weak| weak.upgrade()),
+                data.previous_sibling
+                    .as_ref()
+                    .and_then(|weak| weak.upgrade()),
+                data.next_sibling.clone(),
+            )
+        };
+
+        for link in [parent, previous_sibling, next_sibling].iter() {
+            if let Some(ref link) = *link {
+                check_unborrowed(link, BorrowedNode::Adjacent)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Checks that `node` is a strict ancestor of this node.
+    //
+    // Ancestors that cannot be borrowed are reported as `BorrowedNode::Adjacent`.
+    fn try_has_ancestor(&self, node: &Node<T>) -> Result<bool, TreeError> {
+        for (i, ancestor) in self.try_ancestors().enumerate() {
+            let ancestor = ancestor.map_err(|e| match e {
+                TreeError::AlreadyBorrowed { kind, .. } if i != 0 => TreeError::AlreadyBorrowed {
+                    node: BorrowedNode::Adjacent,
+                    kind,
+                },
+                e => e,
+            })?;
+
+            if i != 0 && ancestor == *node {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Identifies the node while it is alive, for use as a map key.
+    fn address(&self) -> usize {
+        &*self.0 as *const _ as usize
+    }
+
+    // A debug-only check that an insertion will not create a reference cycle.
+    //
+    // Nodes that cannot be borrowed are skipped, since the check must not
+    // introduce new panics. A leaf cannot be an ancestor, so appending new
+    // nodes one by one does not walk up the whole tree each time.
+    fn debug_assert_not_ancestor(&self, new_node: &Node<T>) {
+        debug_assert!(
+            !new_node.has_children() || !self.try_has_ancestor(new_node).unwrap_or(false),
+            "a node cannot be inserted into its own subtree"
+        );
+    }
+
+    // Checks that a node, a new node and all the nodes that an insertion
+    // will update can be borrowed.
+    //
+    // `adjacent` lists the nodes next to this one which links will be updated.
+    // If the new node is already adjacent to this one, the nodes next to it
+    // are checked as a part of `check_detach`.
+    fn check_insert(&self, new_node: &Node<T>, adjacent: &[Neighbor<T>]) -> Result<(), TreeError> {
+        if *self == *new_node {
+            return Err(TreeError::InsertSelf);
+        }
+
+        if self.try_has_ancestor(new_node)? {
+            return Err(TreeError::InsertAncestor);
+        }
+
+        check_unborrowed(&self.0, BorrowedNode::This)?;
+        new_node.check_detach(BorrowedNode::Other)?;
+        for get in adjacent {
+            if let Some(node) = get(self) {
+                check_unborrowed(&node.0, BorrowedNode::Adjacent)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Appends a new child to this node, after existing children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node, the new child, or one of their adjoining nodes is currently borrowed.
+    ///
+    /// In debug builds, also panics if the new child is an ancestor of this node.
+    pub fn append(&self, new_child: Node<T>) {
+        assert!(*self != new_child, "a node cannot be appended to itself");
+        self.debug_assert_not_ancestor(&new_child);
+
+        // Detach first, since the new child can be adjacent to this node.
+        new_child.detach();
+
+        let observed = if observer::is_active() {
+            Some(new_child.clone())
+        } else {
+            None
+        };
+
+        let mut self_borrow = self.0.borrow_mut();
+        let mut last_child_opt = None;
+        {
+            let mut new_child_borrow = new_child.0.borrow_mut();
+            new_child_borrow.parent = Some(Rc::downgrade(&self.0));
+            if let Some(last_child_weak) = self_borrow.last_child.take() {
+                if let Some(last_child_strong) = last_child_weak.upgrade() {
+                    new_child_borrow.previous_sibling = Some(last_child_weak);
+                    last_child_opt = Some(last_child_strong);
+                }
+            }
+            self_borrow.last_child = Some(Rc::downgrade(&new_child.0));
+        }
+
+        if let Some(last_child_strong) = last_child_opt {
+            let mut last_child_borrow = last_child_strong.borrow_mut();
+            debug_assert!(last_child_borrow.next_sibling.is_none());
+            last_child_borrow.next_sibling = Some(new_child.0);
+        } else {
+            // No last child
+            debug_assert!(self_borrow.first_child.is_none());
+            self_borrow.first_child = Some(new_child.0);
+        }
+
+        if let Some(node) = observed {
+            drop(self_borrow);
+            observer::inserted(&node);
+        }
+    }
+
+    /// Like `append`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::InsertSelf` if `new_child` is this node,
+    /// `TreeError::InsertAncestor` if it is an ancestor of this node,
+    /// and `TreeError::AlreadyBorrowed` if the node, the new child,
+    /// or one of their adjoining nodes is currently borrowed.
+    /// The tree is left unchanged in all cases.
+    pub fn try_append(&self, new_child: Node<T>) -> Result<(), TreeError> {
+        self.check_insert(&new_child, &[Node::last_child])?;
+        self.append(new_child);
+        Ok(())
+    }
+
+    /// Prepends a new child to this node, before existing children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node, the new child, or one of their adjoining nodes is currently borrowed.
+    ///
+    /// In debug builds, also panics if the new child is an ancestor of this node.
+    pub fn prepend(&self, new_child: Node<T>) {
+        assert!(*self != new_child, "a node cannot be prepended to itself");
+        self.debug_assert_not_ancestor(&new_child);
+
+        // Detach first, since the new child can be adjacent to this node.
+        new_child.detach();
+
+        let observed = if observer::is_active() {
+            Some(new_child.clone())
+        } else {
+            None
+        };
+
+        let mut self_borrow = self.0.borrow_mut();
+        {
+            let mut new_child_borrow = new_child.0.borrow_mut();
+            new_child_borrow.parent = Some(Rc::downgrade(&self.0));
+            match self_borrow.first_child.take() {
+                Some(first_child_strong) => {
+                    {
+                        let mut first_child_borrow = first_child_strong.borrow_mut();
+                        debug_assert!(first_child_borrow.previous_sibling.is_none());
+                        first_child_borrow.previous_sibling = Some(Rc::downgrade(&new_child.0));
+                    }
+                    new_child_borrow.next_sibling = Some(first_child_strong);
+                }
+                None => {
+                    debug_assert!(self_borrow.first_child.is_none());
+                    self_borrow.last_child = Some(Rc::downgrade(&new_child.0));
+                }
+            }
+        }
+        self_borrow.first_child = Some(new_child.0);
+
+        if let Some(node) = observed {
+            drop(self_borrow);
+            observer::inserted(&node);
+        }
+    }
+
+    /// Like `prepend`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::InsertSelf` if `new_child` is this node,
+    /// `TreeError::InsertAncestor` if it is an ancestor of this node,
+    /// and `TreeError::AlreadyBorrowed` if the node, the new child,
+    /// or one of their adjoining nodes is currently borrowed.
+    /// The tree is left unchanged in all cases.
+    pub fn try_prepend(&self, new_child: Node<T>) -> Result<(), TreeError> {
+        self.check_insert(&new_child, &[Node::first_child])?;
+        self.prepend(new_child);
+        Ok(())
+    }
+
+    /// Inserts a new sibling after this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node, the new sibling, or one of their adjoining nodes is currently borrowed.
+    ///
+    /// In debug builds, also panics if the new sibling is an ancestor of this node.
+    pub fn insert_after(&self, new_sibling: Node<T>) {
+        assert!(
+            *self != new_sibling,
+            "a node cannot be inserted after itself"
+        );
+        self.debug_assert_not_ancestor(&new_sibling);
+
+        // Detach first, since the new sibling can be adjacent to this node.
+        new_sibling.detach();
+
+        let observed = if observer::is_active() {
+            Some(new_sibling.clone())
+        } else {
+            None
+        };
+
+        let mut self_borrow = self.0.borrow_mut();
+        {
+            let mut new_sibling_borrow = new_sibling.0.borrow_mut();
+            new_sibling_borrow.parent = self_borrow.parent.clone();
+            new_sibling_borrow.previous_sibling = Some(Rc::downgrade(&self.0));
+            match self_borrow.next_sibling.take() {
+                Some(next_sibling_strong) => {
+                    {
+                        let mut next_sibling_borrow = next_sibling_strong.borrow_mut();
+                        debug_assert!({
+                            let weak = next_sibling_borrow.previous_sibling.as_ref().unwrap();
+                            Rc::ptr_eq(&weak.upgrade().unwrap(), &self.0)
+                        });
+                        next_sibling_borrow.previous_sibling = Some(Rc::downgrade(&new_sibling.0));
+                    }
+                    new_sibling_borrow.next_sibling = Some(next_sibling_strong);
+                }
+                None => {
+                    if let Some(parent_ref) = self_borrow.parent.as_ref() {
+                        if let Some(parent_strong) = parent_ref.upgrade() {
+                            let mut parent_borrow = parent_strong.borrow_mut();
+                            parent_borrow.last_child = Some(Rc::downgrade(&new_sibling.0));
+                        }
+                    }
+                }
+            }
+        }
+        self_borrow.next_sibling = Some(new_sibling.0);
+
+        if let Some(node) = observed {
+            drop(self_borrow);
+            observer::inserted(&node);
+        }
+    }
+
+    /// Like `insert_after`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::InsertSelf` if `new_sibling` is this node,
+    /// `TreeError::InsertAncestor` if it is an ancestor of this node,
+    /// and `TreeError::AlreadyBorrowed` if the node, the new sibling,
+    /// or one of their adjoining nodes is currently borrowed.
+    /// The tree is left unchanged in all cases.
+    pub fn try_insert_after(&self, new_sibling: Node<T>) -> Result<(), TreeError> {
+        self.check_insert(&new_sibling, &[Node::next_sibling, Node::parent])?;
+        self.insert_after(new_sibling);
+        Ok(())
+    }
+
+    /// Inserts a new sibling before this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node, the new sibling, or one of their adjoining nodes is currently borrowed.
+    ///
+    /// In debug builds, also panics if the new sibling is an ancestor of this node.
+    pub fn insert_before(&self, new_sibling: Node<T>) {
+        assert!(
+            *self != new_sibling,
+            "a node cannot be inserted before itself"
+        );
+        self.debug_assert_not_ancestor(&new_sibling);
+
+        // Detach first, since the new sibling can be adjacent to this node.
+        new_sibling.detach();
+
+        let observed = if observer::is_active() {
+            Some(new_sibling.clone())
+        } else {
+            None
+        };
+
+        let mut self_borrow = self.0.borrow_mut();
+        let mut previous_sibling_opt = None;
+        {
+            let mut new_sibling_borrow = new_sibling.0.borrow_mut();
+            new_sibling_borrow.parent = self_borrow.parent.clone();
+            new_sibling_borrow.next_sibling = Some(self.0.clone());
+            if let Some(previous_sibling_weak) = self_borrow.previous_sibling.take() {
+                if let Some(previous_sibling_strong) = previous_sibling_weak.upgrade() {
+                    new_sibling_borrow.previous_sibling = Some(previous_sibling_weak);
+                    previous_sibling_opt = Some(previous_sibling_strong);
+                }
+            }
+            self_borrow.previous_sibling = Some(Rc::downgrade(&new_sibling.0));
+        }
+
+        if let Some(previous_sibling_strong) = previous_sibling_opt {
             let mut previous_sibling_borrow = previous_sibling_strong.borrow_mut();
             debug_assert!({
                 let rc = previous_sibling_borrow.next_sibling.as_ref().unwrap();
@@ -411,46 +1228,617 @@ impl<T> Node<T> {
                 }
             }
         }
+
+        if let Some(node) = observed {
+            drop(self_borrow);
+            observer::inserted(&node);
+        }
+    }
+
+    /// Like `insert_before`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::InsertSelf` if `new_sibling` is this node,
+    /// `TreeError::InsertAncestor` if it is an ancestor of this node,
+    /// and `TreeError::AlreadyBorrowed` if the node, the new sibling,
+    /// or one of their adjoining nodes is currently borrowed.
+    /// The tree is left unchanged in all cases.
+    pub fn try_insert_before(&self, new_sibling: Node<T>) -> Result<(), TreeError> {
+        self.check_insert(&new_sibling, &[Node::previous_sibling, Node::parent])?;
+        self.insert_before(new_sibling);
+        Ok(())
+    }
+
+    /// Puts a new node in place of this node, which is detached along with its children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_node` is this node,
+    /// or if the node, the new node, or one of their adjoining nodes is currently borrowed.
+    ///
+    /// In debug builds, also panics if the new node is an ancestor of this node.
+    pub fn replace_with(&self, new_node: Node<T>) {
+        assert!(*self != new_node, "a node cannot be replaced with itself");
+        self.insert_before(new_node);
+        self.detach();
+    }
+
+    /// Exchanges the positions of two nodes, which can have different parents.
+    ///
+    /// Children move along with their nodes. Does nothing if `other` is this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the nodes is an ancestor of the other,
+    /// or if one of the nodes or their adjoining nodes is currently borrowed.
+    pub fn swap(&self, other: &Node<T>) {
+        if *self == *other {
+            return;
+        }
+
+        // A cycle would leak the nodes and make traversals loop forever,
+        // so this is checked in release builds too.
+        let is_ancestor = |node: &Node<T>, of: &Node<T>| of.ancestors().skip(1).any(|a| a == *node);
+        assert!(
+            !is_ancestor(self, other) && !is_ancestor(other, self),
+            "a node cannot be swapped with its ancestor"
+        );
+
+        let next_sibling = self.next_sibling();
+        if next_sibling.as_ref() == Some(other) {
+            other.insert_after(self.clone());
+            return;
+        }
+
+        let previous_sibling = self.previous_sibling();
+        if previous_sibling.as_ref() == Some(other) {
+            other.insert_before(self.clone());
+            return;
+        }
+
+        let parent = self.parent();
+        other.insert_before(self.clone());
+        match (next_sibling, previous_sibling, parent) {
+            (Some(next_sibling), _, _) => next_sibling.insert_before(other.clone()),
+            (None, Some(previous_sibling), _) => previous_sibling.insert_after(other.clone()),
+            (None, None, Some(parent)) => parent.append(other.clone()),
+            (None, None, None) => other.detach(),
+        }
+    }
+
+    /// Puts a new parent in place of this node and appends this node to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_parent` is this node,
+    /// or if the node, the new parent, or one of their adjoining nodes is currently borrowed.
+    ///
+    /// In debug builds, also panics if the new parent is an ancestor of this node.
+    pub fn wrap(&self, new_parent: Node<T>) {
+        assert!(*self != new_parent, "a node cannot be wrapped into itself");
+        self.replace_with(new_parent.clone());
+        new_parent.append(self.clone());
+    }
+
+    /// Puts the children of this node in its place and detaches it.
+    ///
+    /// If the node has no parent, its children are detached and become separate roots.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node, one of its children, or one of their adjoining nodes
+    /// is currently borrowed.
+    pub fn unwrap(&self) {
+        let children: Vec<_> = self.children().collect();
+        let has_parent = self.parent().is_some();
+        for child in children {
+            if has_parent {
+                self.insert_before(child);
+            } else {
+                child.detach();
+            }
+        }
+
+        self.detach();
+    }
+
+    /// Appends new children to this node, after existing children, in a single pass.
+    ///
+    /// Equivalent to calling `append` for each node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the new children is this node,
+    /// or if the node, one of the new children, or one of their adjoining nodes
+    /// is currently borrowed.
+    ///
+    /// In debug builds, also panics if one of the new children is an ancestor of this node.
+    pub fn append_all<I>(&self, new_children: I)
+    where
+        I: IntoIterator<Item = Node<T>>,
+    {
+        let new_children: Vec<_> = new_children.into_iter().collect();
+
+        // All the nodes are checked before the first change, so a panic leaves the tree unchanged.
+        for new_child in &new_children {
+            assert!(*self != *new_child, "a node cannot be appended to itself");
+            self.debug_assert_not_ancestor(new_child);
+        }
+
+        if let Err(e) = self.check_append_all(&new_children) {
+            panic!("{}", e);
+        }
+
+        for new_child in &new_children {
+            new_child.detach();
+        }
+
+        // A node that is listed more than once ends up at its last position, like with `append`.
+        // Nodes are linked starting from the end, so a set parent marks an already linked node.
+        let mut unique = Vec::with_capacity(new_children.len());
+        for new_child in new_children.into_iter().rev() {
+            let linked = {
+                let mut new_child_borrow = new_child.0.borrow_mut();
+                if new_child_borrow.parent.is_none() {
+                    new_child_borrow.parent = Some(Rc::downgrade(&self.0));
+                    false
+                } else {
+                    true
+                }
+            };
+
+            if !linked {
+                unique.push(new_child);
+            }
+        }
+        unique.reverse();
+
+        let observed = if observer::is_active() && !unique.is_empty() {
+            Some((unique.clone(), self.last_child()))
+        } else {
+            None
+        };
+
+        self.link_children(unique);
+
+        if let Some((added, previous_sibling)) = observed {
+            let record = MutationRecord::ChildListChanged {
+                parent: self.clone(),
+                added,
+                removed: Vec::new(),
+                previous_sibling,
+                next_sibling: None,
+            };
+            observer::queue(self, record);
+        }
+    }
+
+    /// Detaches all the children of this node and returns them in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its children is currently borrowed.
+    pub fn take_children(&self) -> Vec<Node<T>> {
+        let children = self.unlink_children(0);
+        if observer::is_active() && !children.is_empty() {
+            let record = MutationRecord::ChildListChanged {
+                parent: self.clone(),
+                added: Vec::new(),
+                removed: children.clone(),
+                previous_sibling: None,
+                next_sibling: None,
+            };
+            observer::queue(self, record);
+        }
+
+        children
+    }
+
+    /// Moves the children starting at `index` to the end of `new_parent`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of children,
+    /// or if one of the affected nodes is currently borrowed.
+    ///
+    /// Also panics if `new_parent` is one of the moved children or their descendant.
+    /// The tree is left unchanged in this case.
+    pub fn split_children_at(&self, index: usize, new_parent: &Node<T>) {
+        let count = self.child_count();
+        assert!(index <= count, "index {} is out of bounds", index);
+
+        // Check before unlinking anything, so that a panic does not orphan the tail.
+        let child = new_parent
+            .ancestors()
+            .find(|ancestor| ancestor.parent().as_ref() == Some(self));
+        if let Some(child) = child {
+            assert!(
+                child.index() < index,
+                "children cannot be moved into their own subtree"
+            );
+        }
+
+        let tail = self.unlink_children(index);
+        if observer::is_active() && !tail.is_empty() {
+            let record = MutationRecord::ChildListChanged {
+                parent: self.clone(),
+                added: Vec::new(),
+                removed: tail.clone(),
+                previous_sibling: self.last_child(),
+                next_sibling: None,
+            };
+            observer::queue(self, record);
+        }
+
+        new_parent.append_all(tail);
+    }
+
+    /// Reverses the order of the children of this node in a single pass.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its children is currently borrowed.
+    pub fn reverse_children(&self) {
+        let mut children: Vec<_> = self.children().collect();
+        children.reverse();
+        self.relink_children(children);
+    }
+
+    /// Sorts the children of this node by comparing their data,
+    /// keeping the order of equal children, and relinks them in a single pass.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node or one of its children is currently borrowed.
+    pub fn sort_children_by<F>(&self, mut compare: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let mut children: Vec<_> = self.children().collect();
+        children.sort_by(|a, b| compare(&a.borrow(), &b.borrow()));
+        self.relink_children(children);
+    }
+
+    /// Like `sort_children_by`, but sorts the children of every node in the subtree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the nodes in the subtree is currently borrowed.
+    pub fn sort_descendants_by<F>(&self, mut compare: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let parents: Vec<_> = self.descendants().filter(Node::has_children).collect();
+        for parent in parents {
+            parent.sort_children_by(&mut compare);
+        }
+    }
+
+    // Detaches the children starting at `index` from this node and from each other.
+    fn unlink_children(&self, index: usize) -> Vec<Node<T>> {
+        let mut self_borrow = self.0.borrow_mut();
+        let mut next = if index == 0 {
+            self_borrow.last_child = None;
+            self_borrow.first_child.take()
+        } else {
+            let last = self_borrow
+                .first_child
+                .clone()
+                .map(Node)
+                .and_then(|first| first.following_siblings().nth(index - 1));
+            match last {
+                Some(last) => {
+                    let mut last_borrow = last.0.borrow_mut();
+                    self_borrow.last_child = Some(Rc::downgrade(&last.0));
+                    last_borrow.next_sibling.take()
+                }
+                None => None,
+            }
+        };
+
+        let mut children = Vec::new();
+        while let Some(link) = next {
+            {
+                let mut child_borrow = link.borrow_mut();
+                child_borrow.parent = None;
+                child_borrow.previous_sibling = None;
+                next = child_borrow.next_sibling.take();
+            }
+            children.push(Node(link));
+        }
+
+        children
+    }
+
+    // Links detached unique nodes after the existing children.
+    // Checks that this node, the new children and all the nodes
+    // that detaching and linking them will update can be borrowed.
+    fn check_append_all(&self, new_children: &[Node<T>]) -> Result<(), TreeError> {
+        check_unborrowed(&self.0, BorrowedNode::This)?;
+        if let Some(last_child) = self.last_child() {
+            check_unborrowed(&last_child.0, BorrowedNode::Adjacent)?;
+        }
+
+        for new_child in new_children {
+            new_child.check_detach(BorrowedNode::Other)?;
+        }
+
+        Ok(())
+    }
+
+    fn link_children(&self, new_children: Vec<Node<T>>) {
+        let mut self_borrow = self.0.borrow_mut();
+        let mut last = self_borrow
+            .last_child
+            .as_ref()
+            .and_then(|weak| weak.upgrade());
+        for new_child in new_children {
+            {
+                let mut new_child_borrow = new_child.0.borrow_mut();
+                new_child_borrow.parent = Some(Rc::downgrade(&self.0));
+                new_child_borrow.previous_sibling = last.as_ref().map(Rc::downgrade);
+            }
+
+            match last {
+                Some(ref last) => last.borrow_mut().next_sibling = Some(new_child.0.clone()),
+                None => self_borrow.first_child = Some(new_child.0.clone()),
+            }
+            last = Some(new_child.0);
+        }
+        self_borrow.last_child = last.as_ref().map(Rc::downgrade);
+    }
+
+    // Replaces the children with the same nodes in a new order,
+    // reporting the change as a single record.
+    fn relink_children(&self, children: Vec<Node<T>>) {
+        let removed = self.unlink_children(0);
+        let record = if observer::is_active() && !children.is_empty() {
+            Some(MutationRecord::ChildListChanged {
+                parent: self.clone(),
+                added: children.clone(),
+                removed,
+                previous_sibling: None,
+                next_sibling: None,
+            })
+        } else {
+            None
+        };
+
+        self.link_children(children);
+
+        if let Some(record) = record {
+            observer::queue(self, record);
+        }
+    }
+
+    /// Returns a copy of a current node without children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently mutably borrowed.
+    pub fn make_copy(&self) -> Node<T>
+    where
+        T: Clone,
+    {
+        Node::new(self.borrow().clone())
+    }
+
+    /// Returns a copy of a current node with children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the descendant nodes are currently mutably borrowed.
+    pub fn make_deep_copy(&self) -> Node<T>
+    where
+        T: Clone,
+    {
+        self.map(T::clone)
+    }
+
+    /// Returns a copy of a current node with children,
+    /// where the data of each node is converted by `f`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the descendant nodes are currently mutably borrowed.
+    pub fn map<U, F>(&self, mut f: F) -> Node<U>
+    where
+        F: FnMut(&T) -> U,
+    {
+        enum Never {}
+
+        match self.try_map(|data| Ok::<U, Never>(f(data))) {
+            Ok(node) => node,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like `map`, but stops at the first error returned by `f`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error returned by `f`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the descendant nodes are currently mutably borrowed.
+    pub fn try_map<U, E, F>(&self, mut f: F) -> Result<Node<U>, E>
+    where
+        F: FnMut(&T) -> Result<U, E>,
+    {
+        let root = Node::new(f(&self.borrow())?);
+
+        // Use an explicit stack of new parents to prevent a stack overflow on deep trees.
+        let mut stack = vec![root.clone()];
+        for edge in self.traverse().skip(1) {
+            match edge {
+                NodeEdge::Start(node) => {
+                    let new_node = Node::new(f(&node.borrow())?);
+                    if let Some(parent) = stack.last() {
+                        parent.append(new_node.clone());
+                    }
+                    stack.push(new_node);
+                }
+                NodeEdge::End(_) => {
+                    stack.pop();
+                }
+            }
+        }
+
+        Ok(root)
+    }
+
+    /// Returns a copy of a current node with children,
+    /// along with a map from each original node to its copy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the descendant nodes are currently mutably borrowed.
+    pub fn deep_copy_with_map(&self) -> (Node<T>, CopyMap<T>)
+    where
+        T: Clone,
+    {
+        let root = self.make_copy();
+        let mut map = HashMap::new();
+        map.insert(self.address(), (self.clone(), root.clone()));
+        self.copy_children_into(
+            &root,
+            None,
+            |_| true,
+            |node, copy| {
+                map.insert(node.address(), (node.clone(), copy.clone()));
+            },
+        );
+        (root, CopyMap(map))
     }
 
-    /// Returns a copy of a current node without children.
+    /// Returns a copy of a current node with the descendants for which `f` returns `true`.
+    ///
+    /// When `f` returns `false`, the whole subtree of the node is omitted.
+    /// Returns `None` if `f` returns `false` for this node.
     ///
     /// # Panics
     ///
-    /// Panics if the node is currently mutably borrowed.
-    pub fn make_copy(&self) -> Node<T>
+    /// Panics if any of the descendant nodes are currently mutably borrowed.
+    pub fn deep_copy_filtered<F>(&self, mut f: F) -> Option<Node<T>>
     where
         T: Clone,
+        F: FnMut(&T) -> bool,
     {
-        Node::new(self.borrow().clone())
+        if !f(&self.borrow()) {
+            return None;
+        }
+
+        let root = self.make_copy();
+        self.copy_children_into(&root, None, f, |_, _| {});
+        Some(root)
     }
 
-    /// Returns a copy of a current node with children.
+    /// Returns a copy of a current node with the descendants
+    /// that are at most `depth` levels below it.
+    ///
+    /// A `depth` of zero copies only this node.
     ///
     /// # Panics
     ///
     /// Panics if any of the descendant nodes are currently mutably borrowed.
-    pub fn make_deep_copy(&self) -> Node<T>
+    pub fn deep_copy_to_depth(&self, depth: usize) -> Node<T>
     where
         T: Clone,
     {
-        let mut root = self.make_copy();
-        Node::_make_deep_copy(&mut root, self);
+        let root = self.make_copy();
+        self.copy_children_into(&root, Some(depth), |_| true, |_, _| {});
         root
     }
 
-    fn _make_deep_copy(parent: &mut Node<T>, node: &Node<T>)
+    // Appends copies of the descendants of this node to `copy` in tree order,
+    // calling `copied` with each original node and its copy.
+    fn copy_children_into<F, G>(
+        &self,
+        copy: &Node<T>,
+        max_depth: Option<usize>,
+        mut keep: F,
+        mut copied: G,
+    ) where
+        T: Clone,
+        F: FnMut(&T) -> bool,
+        G: FnMut(&Node<T>, &Node<T>),
+    {
+        let mut traverse = self.traverse_with_depth();
+        if let Some(max_depth) = max_depth {
+            traverse = traverse.max_depth(max_depth);
+        }
+        traverse.next();
+
+        // Use an explicit stack of new parents to prevent a stack overflow on deep trees.
+        let mut stack = vec![copy.clone()];
+        while let Some((_, edge)) = traverse.next() {
+            match edge {
+                NodeEdge::Start(node) => {
+                    if !keep(&node.borrow()) {
+                        // Skip the descendants and the end of the node.
+                        traverse.skip_subtree();
+                        traverse.next();
+                        continue;
+                    }
+
+                    let new_node = node.make_copy();
+                    if let Some(parent) = stack.last() {
+                        parent.append(new_node.clone());
+                    }
+                    copied(&node, &new_node);
+                    stack.push(new_node);
+                }
+                NodeEdge::End(_) => {
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    /// Like `make_copy`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if the node is currently mutably borrowed.
+    pub fn try_make_copy(&self) -> Result<Node<T>, TreeError>
     where
         T: Clone,
     {
-        for child in node.children() {
-            let mut new_node = child.make_copy();
-            parent.append(new_node.clone());
+        Ok(Node::new(self.try_borrow()?.clone()))
+    }
 
-            if child.has_children() {
-                Node::_make_deep_copy(&mut new_node, &child);
+    /// Like `make_deep_copy`, but returns an error instead of panicking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `TreeError::AlreadyBorrowed` if any of the descendant nodes
+    /// are currently mutably borrowed.
+    pub fn try_make_deep_copy(&self) -> Result<Node<T>, TreeError>
+    where
+        T: Clone,
+    {
+        let root = self.try_make_copy()?;
+
+        // Use an explicit stack of new parents to prevent a stack overflow on deep trees.
+        let mut stack = vec![root.clone()];
+        for edge in self.try_traverse().skip(1) {
+            match edge? {
+                NodeEdge::Start(node) => {
+                    let new_node = node.try_make_copy()?;
+                    if let Some(parent) = stack.last() {
+                        parent.append(new_node.clone());
+                    }
+                    stack.push(new_node);
+                }
+                NodeEdge::End(_) => {
+                    stack.pop();
+                }
             }
         }
+
+        Ok(root)
     }
 }
 
@@ -495,154 +1883,507 @@ impl<T> NodeData<T> {
             }
         }
 
-        if let Some(previous_sibling_strong) = previous_sibling_opt {
-            let mut previous_sibling_borrow = previous_sibling_strong.borrow_mut();
-            previous_sibling_borrow.next_sibling = next_sibling_strong;
-        } else if let Some(parent_ref) = parent_weak.as_ref() {
-            if let Some(parent_strong) = parent_ref.upgrade() {
-                let mut parent_borrow = parent_strong.borrow_mut();
-                parent_borrow.first_child = next_sibling_strong;
+        if let Some(previous_sibling_strong) = previous_sibling_opt {
+            let mut previous_sibling_borrow = previous_sibling_strong.borrow_mut();
+            previous_sibling_borrow.next_sibling = next_sibling_strong;
+        } else if let Some(parent_ref) = parent_weak.as_ref() {
+            if let Some(parent_strong) = parent_ref.upgrade() {
+                let mut parent_borrow = parent_strong.borrow_mut();
+                parent_borrow.first_child = next_sibling_strong;
+            }
+        }
+    }
+}
+
+impl<T> Drop for NodeData<T> {
+    fn drop(&mut self) {
+        // Detach all descendant nodes recursively to prevent a stack overflow.
+        if let Some(child) = self.first_child.take() {
+            let mut open_set = vec![child];
+
+            while let Some(node) = open_set.pop() {
+                let mut node_data = node.borrow_mut();
+                if let Some(next_sibling) = node_data.next_sibling.as_ref() {
+                    open_set.push(next_sibling.clone());
+                }
+
+                // Child nodes should be processed if and only if strong_count is one,
+                // which means self is the only reference. Otherwise keep the subtree unchanged.
+                if Rc::strong_count(&node) == 1 {
+                    if let Some(first_child) = node_data.first_child.as_ref() {
+                        open_set.push(first_child.clone());
+                    }
+                }
+
+                node_data.detach();
+            }
+        }
+    }
+}
+
+/// A map from the nodes of a subtree to their copies, returned by `Node::deep_copy_with_map`.
+///
+/// Keeps the original nodes alive.
+pub struct CopyMap<T>(HashMap<usize, (Node<T>, Node<T>)>);
+
+impl<T> CopyMap<T> {
+    /// Returns the copy of `node`, or `None` if it was not copied.
+    pub fn get(&self, node: &Node<T>) -> Option<&Node<T>> {
+        self.0.get(&node.address()).map(|pair| &pair.1)
+    }
+
+    /// Returns the number of copied nodes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if no nodes were copied.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// An iterator of nodes to the ancestors a given node.
+pub struct Ancestors<T>(Option<Node<T>>);
+
+impl<T> Iterator for Ancestors<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        self.0 = node.parent();
+        Some(node)
+    }
+}
+
+/// An iterator of nodes to the siblings before a given node.
+pub struct PrecedingSiblings<T>(Option<Node<T>>);
+
+impl<T> Iterator for PrecedingSiblings<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        self.0 = node.previous_sibling();
+        Some(node)
+    }
+}
+
+/// An iterator of nodes to the siblings after a given node.
+pub struct FollowingSiblings<T>(Option<Node<T>>);
+
+impl<T> Iterator for FollowingSiblings<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        self.0 = node.next_sibling();
+        Some(node)
+    }
+}
+
+/// An iterator of nodes to the nodes after a given node in tree order,
+/// excluding its descendants.
+pub struct Following<T>(Option<Node<T>>);
+
+impl<T> Iterator for Following<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded or one of its ancestors is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        self.0 = node.first_child().or_else(|| node.next_after_subtree(None));
+        Some(node)
+    }
+}
+
+/// An iterator of nodes to the nodes before a given node in reverse tree order,
+/// excluding its ancestors.
+pub struct Preceding<T> {
+    node: Option<Node<T>>,
+    // The nearest ancestor of the given node that is not passed yet.
+    ancestor: Option<Node<T>>,
+}
+
+impl<T> Iterator for Preceding<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if one of the nodes about to be visited is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut node = self.node.take()?;
+        loop {
+            if let Some(sibling) = node.previous_sibling() {
+                let last = sibling.last_descendant();
+                self.node = Some(last.clone());
+                return Some(last);
+            }
+
+            let parent = node.parent()?;
+            if Some(&parent) == self.ancestor.as_ref() {
+                self.ancestor = parent.parent();
+                node = parent;
+                continue;
+            }
+
+            self.node = Some(parent.clone());
+            return Some(parent);
+        }
+    }
+}
+
+/// A double ended iterator of nodes to the children of a given node.
+pub struct Children<T> {
+    next: Option<Node<T>>,
+    next_back: Option<Node<T>>,
+}
+
+impl<T> Children<T> {
+    // true if self.next_back's next sibling is self.next
+    fn finished(&self) -> bool {
+        match self.next_back {
+            Some(ref next_back) => next_back.next_sibling() == self.next,
+            _ => true,
+        }
+    }
+}
+
+impl<T> Iterator for Children<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let node = self.next.take()?;
+        self.next = node.next_sibling();
+        Some(node)
+    }
+}
+
+impl<T> DoubleEndedIterator for Children<T> {
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let node = self.next_back.take()?;
+        self.next_back = node.previous_sibling();
+        Some(node)
+    }
+}
+
+/// A double ended iterator of nodes to a given node and its descendants, in tree order.
+pub struct Descendants<T>(Traverse<T>);
+
+impl<T> Iterator for Descendants<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                Some(NodeEdge::Start(node)) => return Some(node),
+                Some(NodeEdge::End(_)) => {}
+                None => return None,
+            }
+        }
+    }
+}
+
+impl<T> DoubleEndedIterator for Descendants<T> {
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next_back() {
+                Some(NodeEdge::Start(node)) => return Some(node),
+                Some(NodeEdge::End(_)) => {}
+                None => return None,
+            }
+        }
+    }
+}
+
+/// A double ended iterator of nodes to a given node and its descendants,
+/// where each node comes after its descendants.
+pub struct PostOrder<T>(Traverse<T>);
+
+impl<T> Iterator for PostOrder<T> {
+    type Item = Node<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                Some(NodeEdge::End(node)) => return Some(node),
+                Some(NodeEdge::Start(_)) => {}
+                None => return None,
+            }
+        }
+    }
+}
+
+impl<T> DoubleEndedIterator for PostOrder<T> {
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next_back() {
+                Some(NodeEdge::End(node)) => return Some(node),
+                Some(NodeEdge::Start(_)) => {}
+                None => return None,
             }
         }
     }
 }
 
-impl<T> Drop for NodeData<T> {
-    fn drop(&mut self) {
-        // Detach all descendant nodes recursively to prevent a stack overflow.
-        if let Some(child) = self.first_child.take() {
-            let mut open_set = vec![child];
+/// An iterator of nodes to a given node and its descendants, level by level.
+///
+/// Children are read when their parent's level is reached.
+/// The iteration stops if a node about to be yielded was moved to another parent.
+pub struct BreadthFirst<T> {
+    root: Option<Node<T>>,
+    // Yielded nodes which children are not yielded yet, with their depth.
+    queue: VecDeque<(Node<T>, usize)>,
+    parent: Option<(Node<T>, usize)>,
+    next: Option<Node<T>>,
+}
 
-            while let Some(node) = open_set.pop() {
-                let mut node_data = node.borrow_mut();
-                if let Some(next_sibling) = node_data.next_sibling.as_ref() {
-                    open_set.push(next_sibling.clone());
-                }
+impl<T> BreadthFirst<T> {
+    fn next_with_depth(&mut self) -> Option<(Node<T>, usize)> {
+        if let Some(root) = self.root.take() {
+            self.queue.push_back((root.clone(), 0));
+            return Some((root, 0));
+        }
 
-                // Child nodes should be processed if and only if strong_count is one,
-                // which means self is the only reference. Otherwise keep the subtree unchanged.
-                if Rc::strong_count(&node) == 1 {
-                    if let Some(first_child) = node_data.first_child.as_ref() {
-                        open_set.push(first_child.clone());
-                    }
+        loop {
+            if let Some(node) = self.next.take() {
+                let (parent, depth) = self.parent.clone()?;
+                if node.parent() != Some(parent) {
+                    // The tree was mutated.
+                    self.queue.clear();
+                    self.parent = None;
+                    return None;
                 }
 
-                node_data.detach();
+                self.next = node.next_sibling();
+                self.queue.push_back((node.clone(), depth + 1));
+                return Some((node, depth + 1));
             }
+
+            let (parent, depth) = self.queue.pop_front()?;
+            self.next = parent.first_child();
+            self.parent = Some((parent, depth));
         }
     }
 }
 
-/// An iterator of nodes to the ancestors a given node.
-pub struct Ancestors<T>(Option<Node<T>>);
-
-impl<T> Iterator for Ancestors<T> {
+impl<T> Iterator for BreadthFirst<T> {
     type Item = Node<T>;
 
     /// # Panics
     ///
     /// Panics if the node about to be yielded is currently mutably borrowed.
     fn next(&mut self) -> Option<Self::Item> {
-        let node = self.0.take()?;
-        self.0 = node.parent();
-        Some(node)
+        self.next_with_depth().map(|(node, _)| node)
     }
 }
 
-/// An iterator of nodes to the siblings before a given node.
-pub struct PrecedingSiblings<T>(Option<Node<T>>);
+/// An iterator of nodes to a given node and its descendants, grouped by depth.
+///
+/// Follows the same rules as `BreadthFirst`.
+pub struct Levels<T> {
+    nodes: BreadthFirst<T>,
+    pending: Option<(Node<T>, usize)>,
+}
 
-impl<T> Iterator for PrecedingSiblings<T> {
-    type Item = Node<T>;
+impl<T> Iterator for Levels<T> {
+    type Item = Vec<Node<T>>;
 
     /// # Panics
     ///
-    /// Panics if the node about to be yielded is currently mutably borrowed.
+    /// Panics if one of the nodes about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, depth) = match self.pending.take() {
+            Some(pending) => pending,
+            None => self.nodes.next_with_depth()?,
+        };
+
+        let mut level = vec![node];
+        while let Some((node, node_depth)) = self.nodes.next_with_depth() {
+            if node_depth != depth {
+                self.pending = Some((node, node_depth));
+                break;
+            }
+
+            level.push(node);
+        }
+
+        Some(level)
+    }
+}
+
+/// A fallible iterator of nodes to the ancestors a given node.
+///
+/// Yields an error and stops if a node is currently mutably borrowed.
+pub struct TryAncestors<T>(Option<Node<T>>);
+
+impl<T> Iterator for TryAncestors<T> {
+    type Item = Result<Node<T>, TreeError>;
+
     fn next(&mut self) -> Option<Self::Item> {
         let node = self.0.take()?;
-        self.0 = node.previous_sibling();
-        Some(node)
+        Some(node.try_parent().map(|parent| {
+            self.0 = parent;
+            node
+        }))
     }
 }
 
-/// An iterator of nodes to the siblings after a given node.
-pub struct FollowingSiblings<T>(Option<Node<T>>);
+/// A fallible iterator of nodes to the siblings before a given node.
+///
+/// Yields an error and stops if a node is currently mutably borrowed.
+pub struct TryPrecedingSiblings<T>(Option<Node<T>>);
 
-impl<T> Iterator for FollowingSiblings<T> {
-    type Item = Node<T>;
+impl<T> Iterator for TryPrecedingSiblings<T> {
+    type Item = Result<Node<T>, TreeError>;
 
-    /// # Panics
-    ///
-    /// Panics if the node about to be yielded is currently mutably borrowed.
     fn next(&mut self) -> Option<Self::Item> {
         let node = self.0.take()?;
-        self.0 = node.next_sibling();
-        Some(node)
+        Some(node.try_previous_sibling().map(|previous_sibling| {
+            self.0 = previous_sibling;
+            node
+        }))
     }
 }
 
-/// A double ended iterator of nodes to the children of a given node.
-pub struct Children<T> {
+/// A fallible iterator of nodes to the siblings after a given node.
+///
+/// Yields an error and stops if a node is currently mutably borrowed.
+pub struct TryFollowingSiblings<T>(Option<Node<T>>);
+
+impl<T> Iterator for TryFollowingSiblings<T> {
+    type Item = Result<Node<T>, TreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        Some(node.try_next_sibling().map(|next_sibling| {
+            self.0 = next_sibling;
+            node
+        }))
+    }
+}
+
+/// A fallible double ended iterator of nodes to the children of a given node.
+///
+/// Yields an error and stops if a node is currently mutably borrowed.
+pub struct TryChildren<T> {
     next: Option<Node<T>>,
     next_back: Option<Node<T>>,
+    error: Option<TreeError>,
 }
 
-impl<T> Children<T> {
-    // true if self.next_back's next sibling is self.next
-    fn finished(&self) -> bool {
+impl<T> TryChildren<T> {
+    // Ok(true) if self.next_back's next sibling is self.next
+    fn finished(&self) -> Result<bool, TreeError> {
         match self.next_back {
-            Some(ref next_back) => next_back.next_sibling() == self.next,
-            _ => true,
+            Some(ref next_back) => Ok(next_back.try_next_sibling()? == self.next),
+            _ => Ok(true),
         }
     }
-}
 
-impl<T> Iterator for Children<T> {
-    type Item = Node<T>;
+    fn step(&mut self, back: bool) -> Option<Result<Node<T>, TreeError>> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
 
-    /// # Panics
-    ///
-    /// Panics if the node about to be yielded is currently mutably borrowed.
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.finished() {
-            return None;
+        let result = self.finished().and_then(|finished| {
+            if finished {
+                return Ok(None);
+            }
+
+            if back {
+                let node = match self.next_back.take() {
+                    Some(node) => node,
+                    None => return Ok(None),
+                };
+                self.next_back = node.try_previous_sibling()?;
+                Ok(Some(node))
+            } else {
+                let node = match self.next.take() {
+                    Some(node) => node,
+                    None => return Ok(None),
+                };
+                self.next = node.try_next_sibling()?;
+                Ok(Some(node))
+            }
+        });
+
+        match result {
+            Ok(node) => node.map(Ok),
+            Err(e) => {
+                self.next = None;
+                self.next_back = None;
+                Some(Err(e))
+            }
         }
+    }
+}
 
-        let node = self.next.take()?;
-        self.next = node.next_sibling();
-        Some(node)
+impl<T> Iterator for TryChildren<T> {
+    type Item = Result<Node<T>, TreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step(false)
     }
 }
 
-impl<T> DoubleEndedIterator for Children<T> {
-    /// # Panics
-    ///
-    /// Panics if the node about to be yielded is currently mutably borrowed.
+impl<T> DoubleEndedIterator for TryChildren<T> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        if self.finished() {
-            return None;
-        }
-
-        let node = self.next_back.take()?;
-        self.next_back = node.previous_sibling();
-        Some(node)
+        self.step(true)
     }
 }
 
-/// An iterator of nodes to a given node and its descendants, in tree order.
-pub struct Descendants<T>(Traverse<T>);
+/// A fallible iterator of nodes to a given node and its descendants, in tree order.
+///
+/// Yields an error and stops if a node is currently mutably borrowed.
+pub struct TryDescendants<T>(TryTraverse<T>);
 
-impl<T> Iterator for Descendants<T> {
-    type Item = Node<T>;
+impl<T> Iterator for TryDescendants<T> {
+    type Item = Result<Node<T>, TreeError>;
 
-    /// # Panics
-    ///
-    /// Panics if the node about to be yielded is currently mutably borrowed.
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             match self.0.next() {
-                Some(NodeEdge::Start(node)) => return Some(node),
-                Some(NodeEdge::End(_)) => {}
+                Some(Ok(NodeEdge::Start(node))) => return Some(Ok(node)),
+                Some(Ok(NodeEdge::End(_))) => {}
+                Some(Err(e)) => return Some(Err(e)),
                 None => return None,
             }
         }
@@ -666,10 +2407,15 @@ pub enum NodeEdge<T> {
 // Implement PartialEq manually, because we do not need to require T: PartialEq
 impl<T> PartialEq for NodeEdge<T> {
     fn eq(&self, other: &NodeEdge<T>) -> bool {
-        match (self, other) {
-            (&NodeEdge::Start(ref n1), &NodeEdge::Start(ref n2)) => *n1 == *n2,
-            (&NodeEdge::End(ref n1), &NodeEdge::End(ref n2)) => *n1 == *n2,
-            _ => false,
+        match *self {
+            NodeEdge::Start(ref n1) => match *other {
+                NodeEdge::Start(ref n2) => *n1 == *n2,
+                NodeEdge::End(_) => false,
+            },
+            NodeEdge::End(ref n1) => match *other {
+                NodeEdge::End(ref n2) => *n1 == *n2,
+                NodeEdge::Start(_) => false,
+            },
         }
     }
 }
@@ -698,6 +2444,25 @@ impl<T> NodeEdge<T> {
         }
     }
 
+    fn try_next_edge(&self, root: &Node<T>) -> Result<Option<NodeEdge<T>>, TreeError> {
+        Ok(match *self {
+            NodeEdge::Start(ref node) => match node.try_first_child()? {
+                Some(first_child) => Some(NodeEdge::Start(first_child)),
+                None => Some(NodeEdge::End(node.clone())),
+            },
+            NodeEdge::End(ref node) => {
+                if *node == *root {
+                    None
+                } else {
+                    match node.try_next_sibling()? {
+                        Some(next_sibling) => Some(NodeEdge::Start(next_sibling)),
+                        None => node.try_parent()?.map(NodeEdge::End),
+                    }
+                }
+            }
+        })
+    }
+
     fn previous_edge(&self, root: &Node<T>) -> Option<NodeEdge<T>> {
         match *self {
             NodeEdge::End(ref node) => match node.last_child() {
@@ -720,6 +2485,25 @@ impl<T> NodeEdge<T> {
             }
         }
     }
+
+    fn try_previous_edge(&self, root: &Node<T>) -> Result<Option<NodeEdge<T>>, TreeError> {
+        Ok(match *self {
+            NodeEdge::End(ref node) => match node.try_last_child()? {
+                Some(last_child) => Some(NodeEdge::End(last_child)),
+                None => Some(NodeEdge::Start(node.clone())),
+            },
+            NodeEdge::Start(ref node) => {
+                if *node == *root {
+                    None
+                } else {
+                    match node.try_previous_sibling()? {
+                        Some(previous_sibling) => Some(NodeEdge::End(previous_sibling)),
+                        None => node.try_parent()?.map(NodeEdge::Start),
+                    }
+                }
+            }
+        })
+    }
 }
 
 /// A double ended iterator of nodes to a given node and its descendants,
@@ -740,6 +2524,50 @@ impl<T> Traverse<T> {
     }
 }
 
+impl<T> Traverse<T> {
+    /// Skips the descendants of the node which `NodeEdge::Start` was yielded last by `next`.
+    ///
+    /// The `NodeEdge::End` of that node is yielded next.
+    /// Does nothing if the node has no children or if its children have already been entered.
+    /// Skipped nodes can still be yielded by `next_back`, unless it has already
+    /// entered the skipped subtree, which ends the iteration in both directions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the next node is currently mutably borrowed.
+    pub fn skip_subtree(&mut self) {
+        // Right after a start edge, the next edge is the start of the first child.
+        // Any other start edge is either the root or has a previous sibling.
+        let parent = match self.next {
+            Some(NodeEdge::Start(ref node)) if *node != self.root => {
+                if node.previous_sibling().is_some() {
+                    return;
+                }
+
+                node.parent()
+            }
+            _ => None,
+        };
+
+        if let Some(parent) = parent {
+            // `next_back` has already yielded the end of the node,
+            // so all the remaining edges are skipped.
+            let back_inside = match self.next_back {
+                Some(NodeEdge::Start(ref node)) | Some(NodeEdge::End(ref node)) => {
+                    parent.is_ancestor_of(node)
+                }
+                None => false,
+            };
+
+            if back_inside {
+                self.next_back = None;
+            } else {
+                self.next = Some(NodeEdge::End(parent));
+            }
+        }
+    }
+}
+
 impl<T> Iterator for Traverse<T> {
     type Item = NodeEdge<T>;
 
@@ -771,3 +2599,175 @@ impl<T> DoubleEndedIterator for Traverse<T> {
         Some(node)
     }
 }
+
+/// A decision made by the `Node::traverse_filtered` callback.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Visit {
+    /// Continues with the node's children.
+    Continue,
+
+    /// Skips the node's children and continues with its end.
+    SkipChildren,
+
+    /// Ends the traversal.
+    Stop,
+}
+
+/// An iterator of nodes to a given node and its descendants, in tree order,
+/// filtered by a callback.
+pub struct TraverseFiltered<T, F> {
+    traverse: Traverse<T>,
+    filter: F,
+    stopped: bool,
+}
+
+impl<T, F> Iterator for TraverseFiltered<T, F>
+where
+    F: FnMut(&Node<T>) -> Visit,
+{
+    type Item = NodeEdge<T>;
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped {
+            return None;
+        }
+
+        let edge = self.traverse.next()?;
+        if let NodeEdge::Start(ref node) = edge {
+            match (self.filter)(node) {
+                Visit::Continue => {}
+                Visit::SkipChildren => self.traverse.skip_subtree(),
+                Visit::Stop => {
+                    self.stopped = true;
+                    return None;
+                }
+            }
+        }
+
+        Some(edge)
+    }
+}
+
+/// An iterator of nodes to a given node and its descendants, in tree order,
+/// along with their depth relative to the given node.
+///
+/// Both edges of a node have the same depth.
+pub struct TraverseWithDepth<T> {
+    traverse: Traverse<T>,
+    depth: usize,
+    max_depth: Option<usize>,
+}
+
+impl<T> TraverseWithDepth<T> {
+    /// Skips the nodes deeper than `max_depth`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Like `Traverse::skip_subtree`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the next node is currently mutably borrowed.
+    pub fn skip_subtree(&mut self) {
+        self.traverse.skip_subtree();
+    }
+}
+
+impl<T> Iterator for TraverseWithDepth<T> {
+    type Item = (usize, NodeEdge<T>);
+
+    /// # Panics
+    ///
+    /// Panics if the node about to be yielded is currently mutably borrowed.
+    fn next(&mut self) -> Option<Self::Item> {
+        let edge = self.traverse.next()?;
+        match edge {
+            NodeEdge::Start(_) => {
+                let depth = self.depth;
+                if Some(depth) == self.max_depth {
+                    self.traverse.skip_subtree();
+                }
+
+                self.depth += 1;
+                Some((depth, edge))
+            }
+            NodeEdge::End(_) => {
+                // Saturate, since the tree can be mutated during the iteration.
+                self.depth = self.depth.saturating_sub(1);
+                Some((self.depth, edge))
+            }
+        }
+    }
+}
+
+/// A fallible double ended iterator of nodes to a given node and its descendants,
+/// in tree order.
+///
+/// Yields an error and stops if a node is currently mutably borrowed.
+pub struct TryTraverse<T> {
+    root: Node<T>,
+    next: Option<NodeEdge<T>>,
+    next_back: Option<NodeEdge<T>>,
+}
+
+impl<T> TryTraverse<T> {
+    // Ok(true) if self.next_back's next edge is self.next
+    fn finished(&self) -> Result<bool, TreeError> {
+        match self.next_back {
+            Some(ref next_back) => Ok(next_back.try_next_edge(&self.root)? == self.next),
+            _ => Ok(true),
+        }
+    }
+
+    fn step(&mut self, back: bool) -> Option<Result<NodeEdge<T>, TreeError>> {
+        let result = self.finished().and_then(|finished| {
+            if finished {
+                return Ok(None);
+            }
+
+            if back {
+                let edge = match self.next_back.take() {
+                    Some(edge) => edge,
+                    None => return Ok(None),
+                };
+                self.next_back = edge.try_previous_edge(&self.root)?;
+                Ok(Some(edge))
+            } else {
+                let edge = match self.next.take() {
+                    Some(edge) => edge,
+                    None => return Ok(None),
+                };
+                self.next = edge.try_next_edge(&self.root)?;
+                Ok(Some(edge))
+            }
+        });
+
+        match result {
+            Ok(edge) => edge.map(Ok),
+            Err(e) => {
+                self.next = None;
+                self.next_back = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<T> Iterator for TryTraverse<T> {
+    type Item = Result<NodeEdge<T>, TreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step(false)
+    }
+}
+
+impl<T> DoubleEndedIterator for TryTraverse<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.step(true)
+    }
+}
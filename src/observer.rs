@@ -0,0 +1,264 @@
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use super::{Node, WeakLink};
+
+thread_local! {
+    // The number of observers with at least one registration on this thread.
+    //
+    // Nodes cannot leave their thread, so mutations skip looking for observers
+    // while this is zero.
+    static ACTIVE_OBSERVERS: Cell<usize> = Cell::default();
+}
+
+pub(crate) fn is_active() -> bool {
+    ACTIVE_OBSERVERS.with(|count| count.get() != 0)
+}
+
+/// A change to a tree, reported to a `MutationObserver`.
+#[derive(Debug)]
+pub enum MutationRecord<T> {
+    /// Children were added to or removed from `parent`.
+    ///
+    /// Reported by `append`, `prepend`, `insert_after`, `insert_before` and `detach`.
+    /// Moving a node that already has a parent is reported as a removal followed by an addition.
+    ChildListChanged {
+        /// The node which children have changed.
+        parent: Node<T>,
+        /// The added nodes.
+        added: Vec<Node<T>>,
+        /// The removed nodes.
+        removed: Vec<Node<T>>,
+        /// The sibling before the added or removed nodes.
+        previous_sibling: Option<Node<T>>,
+        /// The sibling after the added or removed nodes.
+        next_sibling: Option<Node<T>>,
+    },
+
+    /// The data of `node` has changed.
+    ///
+    /// Reported by `Node::notify_data_changed`.
+    DataChanged {
+        /// The changed node.
+        node: Node<T>,
+    },
+}
+
+impl<T> Clone for MutationRecord<T> {
+    fn clone(&self) -> Self {
+        match *self {
+            MutationRecord::ChildListChanged {
+                ref parent,
+                ref added,
+                ref removed,
+                ref previous_sibling,
+                ref next_sibling,
+            } => MutationRecord::ChildListChanged {
+                parent: parent.clone(),
+                added: added.clone(),
+                removed: removed.clone(),
+                previous_sibling: previous_sibling.clone(),
+                next_sibling: next_sibling.clone(),
+            },
+            MutationRecord::DataChanged { ref node } => {
+                MutationRecord::DataChanged { node: node.clone() }
+            }
+        }
+    }
+}
+
+pub(crate) struct Registration<T> {
+    observer: Weak<RefCell<ObserverData<T>>>,
+    subtree: bool,
+}
+
+struct ObserverData<T> {
+    records: Vec<MutationRecord<T>>,
+    targets: Vec<WeakLink<T>>,
+}
+
+impl<T> Drop for ObserverData<T> {
+    fn drop(&mut self) {
+        if !self.targets.is_empty() {
+            ACTIVE_OBSERVERS.with(|count| count.set(count.get() - 1));
+        }
+    }
+}
+
+/// Collects the changes made to the observed nodes.
+///
+/// Records are queued until they are taken with `take_records`,
+/// so the tree can be freely modified while handling them.
+///
+/// Observers are per thread, like the nodes themselves.
+/// Dropping an observer unregisters it.
+///
+/// Subtree observers are not notified about changes below a node
+/// which data is mutably borrowed at that moment.
+pub struct MutationObserver<T>(Rc<RefCell<ObserverData<T>>>);
+
+impl<T> MutationObserver<T> {
+    /// Creates an observer that does not observe anything yet.
+    pub fn new() -> MutationObserver<T> {
+        MutationObserver(Rc::new(RefCell::new(ObserverData {
+            records: Vec::new(),
+            targets: Vec::new(),
+        })))
+    }
+
+    /// Starts observing a node.
+    ///
+    /// If `subtree` is `true`, changes to all the descendants of the node are reported as well,
+    /// including the ones that are added later.
+    /// Observing the same node again replaces the `subtree` option.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently borrowed.
+    pub fn observe(&self, node: &Node<T>, subtree: bool) {
+        let mut node_borrow = node.0.borrow_mut();
+        let mut observers = take_observers(&mut node_borrow.observers);
+
+        // Drop the registrations of the observers that no longer exist.
+        observers.retain(|registration| registration.observer.upgrade().is_some());
+
+        let existing = observers
+            .iter()
+            .position(|registration| self.is(&registration.observer));
+        match existing {
+            Some(index) => observers[index].subtree = subtree,
+            None => {
+                observers.push(Registration {
+                    observer: Rc::downgrade(&self.0),
+                    subtree,
+                });
+
+                let mut data = self.0.borrow_mut();
+                if data.targets.is_empty() {
+                    ACTIVE_OBSERVERS.with(|count| count.set(count.get() + 1));
+                }
+                data.targets.push(Rc::downgrade(&node.0));
+            }
+        }
+
+        node_borrow.observers = Some(observers.into_boxed_slice());
+    }
+
+    /// Stops observing all nodes and discards the pending records.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the observed nodes is currently borrowed.
+    pub fn disconnect(&self) {
+        let targets = {
+            let mut data = self.0.borrow_mut();
+            data.records.clear();
+            if !data.targets.is_empty() {
+                ACTIVE_OBSERVERS.with(|count| count.set(count.get() - 1));
+            }
+            let mut targets = Vec::new();
+            mem::swap(&mut targets, &mut data.targets);
+            targets
+        };
+
+        for target in targets {
+            if let Some(target) = target.upgrade() {
+                let mut target_borrow = target.borrow_mut();
+                let mut observers = take_observers(&mut target_borrow.observers);
+                observers.retain(|registration| !self.is(&registration.observer));
+                if !observers.is_empty() {
+                    target_borrow.observers = Some(observers.into_boxed_slice());
+                }
+            }
+        }
+    }
+
+    /// Returns the pending records in the order the changes were made and clears them.
+    pub fn take_records(&self) -> Vec<MutationRecord<T>> {
+        let mut records = Vec::new();
+        mem::swap(&mut records, &mut self.0.borrow_mut().records);
+        records
+    }
+
+    fn is(&self, observer: &Weak<RefCell<ObserverData<T>>>) -> bool {
+        match observer.upgrade() {
+            Some(observer) => Rc::ptr_eq(&observer, &self.0),
+            None => false,
+        }
+    }
+}
+
+fn take_observers<T>(observers: &mut Option<Box<[Registration<T>]>>) -> Vec<Registration<T>> {
+    observers
+        .take()
+        .map(|observers| observers.into_vec())
+        .unwrap_or_default()
+}
+
+impl<T> Default for MutationObserver<T> {
+    fn default() -> Self {
+        MutationObserver::new()
+    }
+}
+
+// Delivers a record to the observers of `target` and to the subtree observers of its ancestors.
+//
+// Stops at a node that is currently mutably borrowed,
+// since a mutation must not panic because of an unrelated borrow.
+pub(crate) fn queue<T>(target: &Node<T>, record: MutationRecord<T>) {
+    let mut delivered: Vec<Rc<RefCell<ObserverData<T>>>> = Vec::new();
+    let mut node = Some(target.clone());
+    while let Some(current) = node {
+        node = match current.0.try_borrow() {
+            Ok(data) => {
+                for registration in data.observers.iter().flat_map(|observers| observers.iter()) {
+                    if !registration.subtree && current != *target {
+                        continue;
+                    }
+
+                    if let Some(observer) = registration.observer.upgrade() {
+                        if !delivered.iter().any(|o| Rc::ptr_eq(o, &observer)) {
+                            observer.borrow_mut().records.push(record.clone());
+                            delivered.push(observer);
+                        }
+                    }
+                }
+
+                data.parent
+                    .as_ref()
+                    .and_then(|weak| weak.upgrade())
+                    .map(Node)
+            }
+            Err(_) => break,
+        };
+    }
+}
+
+// Reports that `node` was inserted into its current parent.
+pub(crate) fn inserted<T>(node: &Node<T>) {
+    if let Some(parent) = node.parent() {
+        let record = MutationRecord::ChildListChanged {
+            parent: parent.clone(),
+            added: vec![node.clone()],
+            removed: Vec::new(),
+            previous_sibling: node.previous_sibling(),
+            next_sibling: node.next_sibling(),
+        };
+        queue(&parent, record);
+    }
+}
+
+impl<T> Node<T> {
+    /// Reports a data change of this node to the observers.
+    ///
+    /// Changes made through `borrow_mut` are not tracked automatically,
+    /// so call this after the borrow has ended.
+    ///
+    /// Does nothing if no node is observed.
+    pub fn notify_data_changed(&self) {
+        if is_active() {
+            queue(self, MutationRecord::DataChanged { node: self.clone() });
+        }
+    }
+}
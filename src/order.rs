@@ -0,0 +1,179 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ops::BitOr;
+
+use super::{Node, NodeEdge};
+
+/// The position of a node relative to another one, as a set of flags.
+///
+/// Modeled on the bitmask returned by the DOM `compareDocumentPosition`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct DocumentPosition(u8);
+
+impl DocumentPosition {
+    /// The nodes are in different trees.
+    pub const DISCONNECTED: DocumentPosition = DocumentPosition(1);
+    /// The other node is before this one in tree order.
+    pub const PRECEDING: DocumentPosition = DocumentPosition(2);
+    /// The other node is after this one in tree order.
+    pub const FOLLOWING: DocumentPosition = DocumentPosition(4);
+    /// The other node is an ancestor of this one.
+    pub const CONTAINS: DocumentPosition = DocumentPosition(8);
+    /// The other node is a descendant of this one.
+    pub const CONTAINED_BY: DocumentPosition = DocumentPosition(16);
+    /// Set along with `DISCONNECTED`, since the order of different trees is arbitrary.
+    pub const IMPLEMENTATION_SPECIFIC: DocumentPosition = DocumentPosition(32);
+
+    /// Returns the raw flags, which have the same values as in the DOM.
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns `true` if all the flags of `other` are set.
+    pub fn contains(self, other: DocumentPosition) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if no flags are set, i.e. the nodes are the same.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for DocumentPosition {
+    type Output = DocumentPosition;
+
+    fn bitor(self, other: DocumentPosition) -> DocumentPosition {
+        DocumentPosition(self.0 | other.0)
+    }
+}
+
+impl<T> Node<T> {
+    /// Returns the position of `other` relative to this node.
+    ///
+    /// An ancestor is `CONTAINS | PRECEDING` and a descendant is `CONTAINED_BY | FOLLOWING`.
+    /// Nodes in different trees are `DISCONNECTED | IMPLEMENTATION_SPECIFIC`,
+    /// along with either `PRECEDING` or `FOLLOWING`, which stays the same
+    /// as long as both trees exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn compare_document_position(&self, other: &Node<T>) -> DocumentPosition {
+        if self == other {
+            return DocumentPosition::default();
+        }
+
+        let mut ancestors: Vec<Node<T>> = self.ancestors().collect();
+        let mut other_ancestors: Vec<Node<T>> = other.ancestors().collect();
+        let root = ancestors.pop().unwrap();
+        let other_root = other_ancestors.pop().unwrap();
+        if root != other_root {
+            let order = if other_root.address() < root.address() {
+                DocumentPosition::PRECEDING
+            } else {
+                DocumentPosition::FOLLOWING
+            };
+
+            return DocumentPosition::DISCONNECTED
+                | DocumentPosition::IMPLEMENTATION_SPECIFIC
+                | order;
+        }
+
+        // Remove the common ancestors, starting from the root.
+        loop {
+            match (ancestors.last(), other_ancestors.last()) {
+                (Some(node), Some(other_node)) if node == other_node => {}
+                (None, _) => return DocumentPosition::CONTAINED_BY | DocumentPosition::FOLLOWING,
+                (_, None) => return DocumentPosition::CONTAINS | DocumentPosition::PRECEDING,
+                _ => break,
+            }
+
+            ancestors.pop();
+            other_ancestors.pop();
+        }
+
+        // Both are children of the lowest common ancestor,
+        // so search in both directions until one is found.
+        let sibling = ancestors.pop().unwrap();
+        let other_sibling = other_ancestors.pop();
+        let mut next = sibling.next_sibling();
+        let mut previous = sibling.previous_sibling();
+        loop {
+            if next == other_sibling {
+                return DocumentPosition::FOLLOWING;
+            }
+
+            if previous == other_sibling {
+                return DocumentPosition::PRECEDING;
+            }
+
+            next = next.and_then(|node| node.next_sibling());
+            previous = previous.and_then(|node| node.previous_sibling());
+        }
+    }
+
+    /// Compares the nodes in tree order.
+    ///
+    /// Can be passed to `sort_by`. Nodes in different trees are ordered
+    /// like `compare_document_position` does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn cmp_document_order(&self, other: &Node<T>) -> Ordering {
+        let position = self.compare_document_position(other);
+        if position.is_empty() {
+            Ordering::Equal
+        } else if position.contains(DocumentPosition::PRECEDING) {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        }
+    }
+
+    /// Sorts nodes in tree order and removes the duplicates.
+    ///
+    /// Nodes in different trees are ordered like `compare_document_position` does.
+    ///
+    /// Visits only the ancestors of the nodes and the children of these ancestors,
+    /// so sorting many nodes is faster than with `cmp_document_order`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn sort_in_document_order(nodes: &mut Vec<Node<T>>) {
+        // The addresses of the nodes, which the roots keep alive.
+        let mut targets = HashSet::new();
+        // The addresses of the nodes along with all their ancestors.
+        let mut marked = HashSet::new();
+        let mut roots = Vec::new();
+        for node in nodes.drain(..) {
+            targets.insert(node.address());
+            let mut current = node;
+            while marked.insert(current.address()) {
+                match current.parent() {
+                    Some(parent) => current = parent,
+                    None => {
+                        roots.push(current);
+                        break;
+                    }
+                }
+            }
+        }
+
+        roots.sort_by_key(|root| root.address());
+        for root in roots {
+            let mut traverse = root.traverse();
+            while let Some(edge) = traverse.next() {
+                if let NodeEdge::Start(node) = edge {
+                    if !marked.contains(&node.address()) {
+                        traverse.skip_subtree();
+                    } else if targets.contains(&node.address()) {
+                        nodes.push(node);
+                    }
+                }
+            }
+        }
+    }
+}
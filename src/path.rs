@@ -0,0 +1,144 @@
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::Node;
+
+/// A position of a node in a tree, as a list of child indices starting from the root.
+///
+/// An empty path refers to the root itself.
+/// Paths from the same root are ordered in tree order.
+///
+/// Formatted and parsed as `/0/3/1`, where an empty path is `/`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+pub struct TreePath(Vec<usize>);
+
+impl TreePath {
+    /// Creates an empty path, which refers to the root.
+    pub fn new() -> TreePath {
+        TreePath(Vec::new())
+    }
+
+    /// Returns the child indices of the path.
+    pub fn indices(&self) -> &[usize] {
+        &self.0
+    }
+
+    /// Returns the number of child indices in the path.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the path refers to the root.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Appends a child index to the path.
+    pub fn push(&mut self, index: usize) {
+        self.0.push(index);
+    }
+
+    /// Removes the last child index from the path, turning it into the path to the parent.
+    pub fn pop(&mut self) -> Option<usize> {
+        self.0.pop()
+    }
+}
+
+impl From<Vec<usize>> for TreePath {
+    fn from(indices: Vec<usize>) -> Self {
+        TreePath(indices)
+    }
+}
+
+impl fmt::Display for TreePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("/");
+        }
+
+        for index in &self.0 {
+            write!(f, "/{}", index)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error returned when parsing a `TreePath` fails.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParsePathError;
+
+impl fmt::Display for ParsePathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid tree path")
+    }
+}
+
+impl error::Error for ParsePathError {
+    fn description(&self) -> &str {
+        "invalid tree path"
+    }
+}
+
+impl FromStr for TreePath {
+    type Err = ParsePathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('/') {
+            return Err(ParsePathError);
+        }
+
+        let s = &s[1..];
+        if s.is_empty() {
+            return Ok(TreePath::new());
+        }
+
+        let mut indices = Vec::new();
+        for part in s.split('/') {
+            // `usize::from_str` accepts a leading `+`.
+            if part.starts_with('+') {
+                return Err(ParsePathError);
+            }
+
+            indices.push(part.parse().map_err(|_| ParsePathError)?);
+        }
+
+        Ok(TreePath(indices))
+    }
+}
+
+impl<T> Node<T> {
+    /// Returns the path from the root of the tree to this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node, one of its ancestors or one of their preceding siblings
+    /// is currently mutably borrowed.
+    pub fn path(&self) -> TreePath {
+        let mut indices: Vec<usize> = self
+            .ancestors()
+            .take_while(|node| node.parent().is_some())
+            .map(|node| node.index())
+            .collect();
+        indices.reverse();
+        TreePath(indices)
+    }
+
+    /// Returns the node at `path`, treating this node as the root.
+    ///
+    /// Returns `None` if there is no such node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the nodes on the path or one of their children
+    /// is currently mutably borrowed.
+    pub fn resolve(&self, path: &TreePath) -> Option<Node<T>> {
+        let mut node = self.clone();
+        for &index in &path.0 {
+            node = node.nth_child(index)?;
+        }
+
+        Some(node)
+    }
+}
@@ -0,0 +1,650 @@
+/*!
+CSS selectors.
+
+`Node::select` and `Node::matches` evaluate a comma-separated list of CSS selectors
+against a tree which data implements `Element`.
+Nodes for which `Element::name` returns `None`, like text nodes, never match
+and are skipped when counting siblings.
+
+Supported are:
+
+* type selectors and `*`,
+* `#id` and `.class` selectors, using the `id` and `class` attributes,
+* attribute selectors with the `=`, `~=`, `|=`, `^=`, `$=` and `*=` operators
+  and an optional `i` flag for case-insensitive values,
+* the descendant, child (`>`), next-sibling (`+`) and subsequent-sibling (`~`) combinators,
+* the `:first-child`, `:last-child`, `:only-child`, `:nth-child(an+b)`,
+  `:nth-last-child(an+b)` and `:not(...)` pseudo-classes.
+
+Names and values are compared as is, and escapes are not supported.
+*/
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::Node;
+
+/// The node data that CSS selectors can match.
+pub trait Element {
+    /// Returns the name of an element node, or `None` for other nodes.
+    fn name(&self) -> Option<&str>;
+
+    /// Returns the value of an attribute of an element node.
+    fn attribute(&self, name: &str) -> Option<&str>;
+}
+
+/// An error returned when parsing a selector list fails.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// The byte offset of the error in the selector list.
+    pub position: usize,
+    message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        "invalid selector"
+    }
+}
+
+/// A parsed list of CSS selectors, which can be matched many times.
+#[derive(Clone, Debug)]
+pub struct SelectorList(Vec<Selector>);
+
+impl SelectorList {
+    /// Parses a comma-separated list of selectors.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `selectors` is not a valid or supported selector list.
+    pub fn parse(selectors: &str) -> Result<SelectorList, ParseError> {
+        let mut parser = Parser {
+            s: selectors,
+            pos: 0,
+        };
+
+        let list = parser.parse_list()?;
+        if parser.pos != selectors.len() {
+            return Err(parser.error("unexpected character"));
+        }
+
+        Ok(SelectorList(list))
+    }
+
+    /// Returns `true` if the node matches any of the selectors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn matches<T: Element>(&self, node: &Node<T>) -> bool {
+        is_element(node) && matches_any(&self.0, node)
+    }
+
+    /// Returns the descendants of the node that match any of the selectors, in tree order.
+    ///
+    /// The node itself is not included, but its ancestors can match
+    /// the left-hand side of a combinator, like with `querySelectorAll`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn select<T: Element>(&self, node: &Node<T>) -> Vec<Node<T>> {
+        node.descendants()
+            .skip(1)
+            .filter(|node| self.matches(node))
+            .collect()
+    }
+}
+
+impl FromStr for SelectorList {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SelectorList::parse(s)
+    }
+}
+
+impl<T: Element> Node<T> {
+    /// Returns the descendants of this node that match the selector list, in tree order.
+    ///
+    /// See `SelectorList::select` for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `selectors` is not a valid or supported selector list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn select(&self, selectors: &str) -> Result<Vec<Node<T>>, ParseError> {
+        Ok(SelectorList::parse(selectors)?.select(self))
+    }
+
+    /// Returns `true` if this node matches the selector list.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `selectors` is not a valid or supported selector list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn matches(&self, selectors: &str) -> Result<bool, ParseError> {
+        Ok(SelectorList::parse(selectors)?.matches(self))
+    }
+}
+
+// A complex selector, such as `div > p.note`.
+#[derive(Clone, Debug)]
+struct Selector {
+    compounds: Vec<Compound>,
+    // The combinators between the compounds.
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+#[derive(Clone, Debug)]
+struct Compound {
+    // `None` matches any element.
+    name: Option<String>,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Clone, Debug)]
+enum Condition {
+    Attribute {
+        name: String,
+        // The operator and the value, if any.
+        value: Option<(Operator, String)>,
+        case_insensitive: bool,
+    },
+    NthChild {
+        a: i64,
+        b: i64,
+        from_end: bool,
+    },
+    OnlyChild,
+    Not(Vec<Selector>),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operator {
+    Equal,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+fn is_element<T: Element>(node: &Node<T>) -> bool {
+    node.borrow().name().is_some()
+}
+
+fn matches_any<T: Element>(selectors: &[Selector], node: &Node<T>) -> bool {
+    selectors
+        .iter()
+        .any(|selector| matches_selector(selector, selector.compounds.len() - 1, node))
+}
+
+// Matches the compounds up to `index` from right to left, trying all the possible
+// ancestors or siblings for each combinator.
+fn matches_selector<T: Element>(selector: &Selector, index: usize, node: &Node<T>) -> bool {
+    if !matches_compound(&selector.compounds[index], node) {
+        return false;
+    }
+
+    if index == 0 {
+        return true;
+    }
+
+    let matches_previous = |node: Node<T>| matches_selector(selector, index - 1, &node);
+    match selector.combinators[index - 1] {
+        Combinator::Descendant => node
+            .ancestors()
+            .skip(1)
+            .take_while(is_element)
+            .any(matches_previous),
+        Combinator::Child => match node.parent() {
+            Some(parent) => is_element(&parent) && matches_previous(parent),
+            None => false,
+        },
+        Combinator::NextSibling => match node.preceding_siblings().skip(1).find(is_element) {
+            Some(sibling) => matches_previous(sibling),
+            None => false,
+        },
+        Combinator::SubsequentSibling => node
+            .preceding_siblings()
+            .skip(1)
+            .filter(is_element)
+            .any(matches_previous),
+    }
+}
+
+fn matches_compound<T: Element>(compound: &Compound, node: &Node<T>) -> bool {
+    {
+        let data = node.borrow();
+        let name = match data.name() {
+            Some(name) => name,
+            None => return false,
+        };
+
+        if let Some(ref expected) = compound.name {
+            if expected != name {
+                return false;
+            }
+        }
+    }
+
+    compound
+        .conditions
+        .iter()
+        .all(|condition| matches_condition(condition, node))
+}
+
+fn matches_condition<T: Element>(condition: &Condition, node: &Node<T>) -> bool {
+    match *condition {
+        Condition::Attribute {
+            ref name,
+            ref value,
+            case_insensitive,
+        } => {
+            let data = node.borrow();
+            let actual = match data.attribute(name) {
+                Some(actual) => actual,
+                None => return false,
+            };
+
+            match *value {
+                Some((op, ref expected)) if case_insensitive => {
+                    matches_value(op, &actual.to_lowercase(), &expected.to_lowercase())
+                }
+                Some((op, ref expected)) => matches_value(op, actual, expected),
+                None => true,
+            }
+        }
+        Condition::NthChild { a, b, from_end } => {
+            let index = if from_end {
+                node.following_siblings().skip(1).filter(is_element).count()
+            } else {
+                node.preceding_siblings().skip(1).filter(is_element).count()
+            };
+
+            matches_nth(a, b, index as i64 + 1)
+        }
+        Condition::OnlyChild => {
+            !node
+                .preceding_siblings()
+                .skip(1)
+                .any(|node| is_element(&node))
+                && !node
+                    .following_siblings()
+                    .skip(1)
+                    .any(|node| is_element(&node))
+        }
+        Condition::Not(ref selectors) => !matches_any(selectors, node),
+    }
+}
+
+fn matches_value(op: Operator, actual: &str, expected: &str) -> bool {
+    match op {
+        Operator::Equal => actual == expected,
+        Operator::Includes => actual.split_whitespace().any(|word| word == expected),
+        Operator::DashMatch => {
+            actual == expected
+                || (actual.starts_with(expected) && actual[expected.len()..].starts_with('-'))
+        }
+        // Empty values never match, per the specification.
+        Operator::Prefix => !expected.is_empty() && actual.starts_with(expected),
+        Operator::Suffix => !expected.is_empty() && actual.ends_with(expected),
+        Operator::Substring => !expected.is_empty() && actual.contains(expected),
+    }
+}
+
+// Returns `true` if `index` is `a * n + b` for some non-negative `n`.
+//
+// `a` and `b` can be any integers, so an overflow means there is no such `n`.
+fn matches_nth(a: i64, b: i64, index: i64) -> bool {
+    if a == 0 {
+        return index == b;
+    }
+
+    let n = match index.checked_sub(b) {
+        Some(n) => n,
+        None => return false,
+    };
+
+    match (n.checked_rem(a), n.checked_div(a)) {
+        (Some(0), Some(n)) => n >= 0,
+        _ => false,
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> ParseError {
+        if self.pos == self.s.len() {
+            ParseError {
+                position: self.pos,
+                message: "unexpected end of selector",
+            }
+        } else {
+            ParseError {
+                position: self.pos,
+                message,
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    // Returns `true` if any whitespace was skipped.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+
+            self.pos += c.len_utf8();
+        }
+
+        self.pos != start
+    }
+
+    fn parse_ident(&mut self) -> Result<String, ParseError> {
+        let rest = &self.s[self.pos..];
+        let start = if rest.starts_with('-') { 1 } else { 0 };
+        let is_valid = match rest[start..].chars().next() {
+            Some(c) => c.is_alphabetic() || c == '_' || c as u32 > 0x7f,
+            None => false,
+        };
+
+        if !is_valid {
+            return Err(self.error("expected an identifier"));
+        }
+
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c as u32 > 0x7f))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<Selector>, ParseError> {
+        let mut list = Vec::new();
+        loop {
+            self.skip_whitespace();
+            list.push(self.parse_selector()?);
+            self.skip_whitespace();
+            if !self.eat(',') {
+                return Ok(list);
+            }
+        }
+    }
+
+    fn parse_selector(&mut self) -> Result<Selector, ParseError> {
+        let mut selector = Selector {
+            compounds: vec![self.parse_compound()?],
+            combinators: Vec::new(),
+        };
+
+        loop {
+            let has_whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                Some(',') | Some(')') | None => return Ok(selector),
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(_) if has_whitespace => Combinator::Descendant,
+                Some(_) => return Err(self.error("unexpected character")),
+            };
+
+            if combinator != Combinator::Descendant {
+                self.pos += 1;
+                self.skip_whitespace();
+            }
+
+            selector.combinators.push(combinator);
+            selector.compounds.push(self.parse_compound()?);
+        }
+    }
+
+    fn parse_compound(&mut self) -> Result<Compound, ParseError> {
+        let start = self.pos;
+        let name = if self.eat('*') {
+            None
+        } else {
+            self.parse_ident().ok()
+        };
+
+        let mut compound = Compound {
+            name,
+            conditions: Vec::new(),
+        };
+
+        loop {
+            let condition = match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    Condition::Attribute {
+                        name: "id".to_string(),
+                        value: Some((Operator::Equal, self.parse_ident()?)),
+                        case_insensitive: false,
+                    }
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    Condition::Attribute {
+                        name: "class".to_string(),
+                        value: Some((Operator::Includes, self.parse_ident()?)),
+                        case_insensitive: false,
+                    }
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    self.parse_attribute()?
+                }
+                Some(':') => {
+                    self.pos += 1;
+                    self.parse_pseudo_class()?
+                }
+                _ => break,
+            };
+
+            compound.conditions.push(condition);
+        }
+
+        if self.pos == start {
+            return Err(self.error("expected a selector"));
+        }
+
+        Ok(compound)
+    }
+
+    fn parse_attribute(&mut self) -> Result<Condition, ParseError> {
+        self.skip_whitespace();
+        let name = self.parse_ident()?;
+        self.skip_whitespace();
+
+        let op = match self.peek() {
+            Some('=') => Some(Operator::Equal),
+            Some('~') => Some(Operator::Includes),
+            Some('|') => Some(Operator::DashMatch),
+            Some('^') => Some(Operator::Prefix),
+            Some('$') => Some(Operator::Suffix),
+            Some('*') => Some(Operator::Substring),
+            _ => None,
+        };
+
+        let mut value = None;
+        let mut case_insensitive = false;
+        if let Some(op) = op {
+            self.pos += 1;
+            if op != Operator::Equal {
+                self.expect('=')?;
+            }
+
+            self.skip_whitespace();
+            value = Some((op, self.parse_value()?));
+            self.skip_whitespace();
+            if self.eat('i') || self.eat('I') {
+                case_insensitive = true;
+                self.skip_whitespace();
+            }
+        }
+
+        self.expect(']')?;
+        Ok(Condition::Attribute {
+            name,
+            value,
+            case_insensitive,
+        })
+    }
+
+    fn parse_value(&mut self) -> Result<String, ParseError> {
+        let quote = match self.peek() {
+            Some(c) if c == '\'' || c == '"' => c,
+            _ => return self.parse_ident(),
+        };
+
+        let rest = &self.s[self.pos + 1..];
+        match rest.find(quote) {
+            Some(len) => {
+                self.pos += len + 2;
+                Ok(rest[..len].to_string())
+            }
+            None => Err(self.error("unterminated string")),
+        }
+    }
+
+    fn parse_pseudo_class(&mut self) -> Result<Condition, ParseError> {
+        let start = self.pos;
+        let name = self.parse_ident()?;
+        let nth = |a, b, from_end| Condition::NthChild { a, b, from_end };
+        let condition = match name.as_str() {
+            "first-child" => nth(0, 1, false),
+            "last-child" => nth(0, 1, true),
+            "only-child" => Condition::OnlyChild,
+            "nth-child" | "nth-last-child" => {
+                self.expect('(')?;
+                let (a, b) = self.parse_nth()?;
+                self.expect(')')?;
+                nth(a, b, name == "nth-last-child")
+            }
+            "not" => {
+                self.expect('(')?;
+                let list = self.parse_list()?;
+                self.expect(')')?;
+                Condition::Not(list)
+            }
+            _ => {
+                self.pos = start;
+                return Err(self.error("unsupported pseudo-class"));
+            }
+        };
+
+        Ok(condition)
+    }
+
+    // Parses the `an+b` argument, which can also be `odd` or `even`.
+    fn parse_nth(&mut self) -> Result<(i64, i64), ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let len = self.s[start..].find(')').unwrap_or(self.s.len() - start);
+        let arg: String = self.s[start..start + len]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+
+        // Accepts only digits with an optional sign.
+        let parse_int = |s: &str| -> Option<i64> {
+            let (sign, digits) = match s.chars().next() {
+                Some('-') => (-1, &s[1..]),
+                Some('+') => (1, &s[1..]),
+                _ => (1, s),
+            };
+
+            if digits.is_empty() || !digits.chars().all(|c| "0123456789".contains(c)) {
+                return None;
+            }
+
+            digits.parse::<i64>().ok().map(|n| sign * n)
+        };
+
+        let parsed = match arg.as_str() {
+            "odd" => Some((2, 1)),
+            "even" => Some((2, 0)),
+            _ => match arg.find('n') {
+                Some(index) => {
+                    let a = match &arg[..index] {
+                        "" | "+" => Some(1),
+                        "-" => Some(-1),
+                        a => parse_int(a),
+                    };
+
+                    let b = &arg[index + 1..];
+                    let b = if b.is_empty() {
+                        Some(0)
+                    } else if b.starts_with('+') || b.starts_with('-') {
+                        parse_int(b)
+                    } else {
+                        None
+                    };
+
+                    match (a, b) {
+                        (Some(a), Some(b)) => Some((a, b)),
+                        _ => None,
+                    }
+                }
+                None => parse_int(&arg).map(|b| (0, b)),
+            },
+        };
+
+        match parsed {
+            Some(nth) => {
+                self.pos = start + len;
+                Ok(nth)
+            }
+            None => Err(self.error("invalid an+b expression")),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// Serialization of whole subtrees, enabled by the `serde` feature.
+//
+// A node is represented as `{ data: T, children: [Node] }`.
+//
+// The format is nested, so serializers and deserializers themselves go one level deeper
+// for each tree level, and some of them limit the nesting depth.
+// The code here does not walk the tree with `children()` on its own:
+// all the nodes of a subtree are visited by a single `traverse` iterator,
+// and each child is appended to its parent as soon as it is built.
+// A partially built tree is dropped iteratively by `NodeData::drop` when deserialization fails.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+
+use super::{Node, NodeEdge, Traverse};
+
+const FIELDS: &[&str] = &["data", "children"];
+
+impl<T: Serialize> Serialize for Node<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut traverse = self.traverse();
+        // Skip the start of this node, so the next edge belongs to its first child.
+        traverse.next();
+        SerializeNode {
+            node: self.clone(),
+            traverse: &RefCell::new(traverse),
+        }
+        .serialize(serializer)
+    }
+}
+
+// A node which children are read from the shared traversal.
+struct SerializeNode<'a, T: 'a> {
+    node: Node<T>,
+    traverse: &'a RefCell<Traverse<T>>,
+}
+
+impl<'a, T: Serialize> Serialize for SerializeNode<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Node", 2)?;
+        state.serialize_field("data", &*self.node.borrow())?;
+        state.serialize_field(
+            "children",
+            &SerializeChildren {
+                node: &self.node,
+                traverse: self.traverse,
+            },
+        )?;
+        state.end()
+    }
+}
+
+struct SerializeChildren<'a, T: 'a> {
+    node: &'a Node<T>,
+    traverse: &'a RefCell<Traverse<T>>,
+}
+
+impl<'a, T: Serialize> Serialize for SerializeChildren<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Some formats require the length upfront.
+        let mut seq = serializer.serialize_seq(Some(self.node.children().count()))?;
+        loop {
+            // Every child consumes its own subtree, so the next edge is either
+            // the start of the next child or the end of this node.
+            let edge = self.traverse.borrow_mut().next();
+            match edge {
+                Some(NodeEdge::Start(child)) => seq.serialize_element(&SerializeNode {
+                    node: child,
+                    traverse: self.traverse,
+                })?,
+                Some(NodeEdge::End(_)) | None => break,
+            }
+        }
+        seq.end()
+    }
+}
+
+/// Builds a tree with `Node::new` and `Node::append`, preserving the children order.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Node<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Node", FIELDS, NodeVisitor(PhantomData))
+    }
+}
+
+enum Field {
+    Data,
+    Children,
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl<'de> Visitor<'de> for FieldVisitor {
+            type Value = Field;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("`data` or `children`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Field, E> {
+                match value {
+                    "data" => Ok(Field::Data),
+                    "children" => Ok(Field::Children),
+                    _ => Err(de::Error::unknown_field(value, FIELDS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+struct NodeVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for NodeVisitor<T> {
+    type Value = Node<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct Node")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node<T>, A::Error> {
+        let node = match seq.next_element()? {
+            Some(data) => Node::new(data),
+            None => return Err(de::Error::invalid_length(0, &self)),
+        };
+
+        match seq.next_element_seed(AppendChildren(&node))? {
+            Some(()) => Ok(node),
+            None => Err(de::Error::invalid_length(1, &self)),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node<T>, A::Error> {
+        let mut node = None;
+        // Children that precede the data, which are appended once the node is created.
+        let mut early_children: Option<Vec<Node<T>>> = None;
+        let mut has_children = false;
+        while let Some(key) = map.next_key()? {
+            match key {
+                Field::Data => {
+                    if node.is_some() {
+                        return Err(de::Error::duplicate_field("data"));
+                    }
+                    node = Some(Node::new(map.next_value()?));
+                }
+                Field::Children => {
+                    if has_children {
+                        return Err(de::Error::duplicate_field("children"));
+                    }
+                    has_children = true;
+                    match node {
+                        Some(ref node) => map.next_value_seed(AppendChildren(node))?,
+                        None => early_children = Some(map.next_value()?),
+                    }
+                }
+            }
+        }
+
+        let node = node.ok_or_else(|| de::Error::missing_field("data"))?;
+        if !has_children {
+            return Err(de::Error::missing_field("children"));
+        }
+
+        if let Some(children) = early_children {
+            node.append_all(children);
+        }
+
+        Ok(node)
+    }
+}
+
+// Deserializes a sequence of nodes and appends them to a parent.
+struct AppendChildren<'a, T: 'a>(&'a Node<T>);
+
+impl<'a, 'de, T: Deserialize<'de>> DeserializeSeed<'de> for AppendChildren<'a, T> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'a, 'de, T: Deserialize<'de>> Visitor<'de> for AppendChildren<'a, T> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of nodes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(child) = seq.next_element::<Node<T>>()? {
+            self.0.append(child);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,789 @@
+/*!
+A thread-safe version of the tree.
+
+`SyncNode` mirrors the `Node` API, but uses `Arc` for reference counting
+and `RwLock` instead of `RefCell` for interior mutability,
+so a tree can be shared between threads when `T: Send + Sync`.
+
+The differences from `Node` are:
+
+* Where `Node` would panic because a node is already borrowed,
+  `SyncNode` blocks until the other borrow is released.
+  This deadlocks if the borrow is held by the current thread.
+* Each operation locks only a single node at a time, so operations cannot
+  deadlock each other. As a consequence, a structural modification
+  (`append`, `detach`, etc.) is not atomic: other threads can observe
+  a partially updated tree, and concurrent modifications of the same nodes
+  must be synchronized by the caller.
+* A panic while a node is borrowed does not poison it, just like with `RefCell`.
+*/
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, Weak};
+
+type Link<T> = Arc<RwLock<NodeData<T>>>;
+type WeakLink<T> = Weak<RwLock<NodeData<T>>>;
+// A parent, a previous sibling and a next sibling of a detached node.
+type Links<T> = (Option<WeakLink<T>>, Option<WeakLink<T>>, Option<Link<T>>);
+
+/// A thread-safe reference to a node holding a value of type `T`. Nodes form a tree.
+///
+/// Internally, this uses atomic reference counting for lifetime tracking
+/// and `std::sync::RwLock` for interior mutability.
+///
+/// **Note:** Cloning a `SyncNode` only increments a reference count. It does not copy the data.
+pub struct SyncNode<T>(Link<T>);
+
+/// A thread-safe weak reference to a node holding a value of type `T`.
+pub struct SyncWeakNode<T>(WeakLink<T>);
+
+struct NodeData<T> {
+    parent: Option<WeakLink<T>>,
+    first_child: Option<Link<T>>,
+    last_child: Option<WeakLink<T>>,
+    previous_sibling: Option<WeakLink<T>>,
+    next_sibling: Option<Link<T>>,
+    data: T,
+}
+
+fn read<'a, T>(link: &'a Link<T>) -> RwLockReadGuard<'a, NodeData<T>> {
+    link.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<'a, T>(link: &'a Link<T>) -> RwLockWriteGuard<'a, NodeData<T>> {
+    link.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A shared reference to a node's data, returned by `SyncNode::borrow`.
+///
+/// The node is read-locked while this guard is alive.
+pub struct SyncRef<'a, T: 'a>(RwLockReadGuard<'a, NodeData<T>>);
+
+impl<'a, T> Deref for SyncRef<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.data
+    }
+}
+
+/// A unique/mutable reference to a node's data, returned by `SyncNode::borrow_mut`.
+///
+/// The node is write-locked while this guard is alive.
+pub struct SyncRefMut<'a, T: 'a>(RwLockWriteGuard<'a, NodeData<T>>);
+
+impl<'a, T> Deref for SyncRefMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.data
+    }
+}
+
+impl<'a, T> DerefMut for SyncRefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0.data
+    }
+}
+
+/// Cloning a `SyncNode` only increments a reference count. It does not copy the data.
+impl<T> Clone for SyncNode<T> {
+    fn clone(&self) -> Self {
+        SyncNode(Arc::clone(&self.0))
+    }
+}
+
+impl<T> PartialEq for SyncNode<T> {
+    fn eq(&self, other: &SyncNode<T>) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SyncNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.borrow(), f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for SyncNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&*self.borrow(), f)
+    }
+}
+
+impl<T> SyncNode<T> {
+    /// Creates a new node from its associated data.
+    pub fn new(data: T) -> SyncNode<T> {
+        SyncNode(Arc::new(RwLock::new(NodeData {
+            parent: None,
+            first_child: None,
+            last_child: None,
+            previous_sibling: None,
+            next_sibling: None,
+            data,
+        })))
+    }
+
+    /// Returns a weak reference to a node.
+    pub fn downgrade(&self) -> SyncWeakNode<T> {
+        SyncWeakNode(Arc::downgrade(&self.0))
+    }
+
+    /// Returns a parent node, unless this node is the root of the tree.
+    ///
+    /// Blocks while the node is mutably borrowed.
+    pub fn parent(&self) -> Option<SyncNode<T>> {
+        Some(SyncNode(read(&self.0).parent.as_ref()?.upgrade()?))
+    }
+
+    /// Returns a first child of this node, unless it has no child.
+    ///
+    /// Blocks while the node is mutably borrowed.
+    pub fn first_child(&self) -> Option<SyncNode<T>> {
+        Some(SyncNode(read(&self.0).first_child.as_ref()?.clone()))
+    }
+
+    /// Returns a last child of this node, unless it has no child.
+    ///
+    /// Blocks while the node is mutably borrowed.
+    pub fn last_child(&self) -> Option<SyncNode<T>> {
+        Some(SyncNode(read(&self.0).last_child.as_ref()?.upgrade()?))
+    }
+
+    /// Returns the previous sibling of this node, unless it is a first child.
+    ///
+    /// Blocks while the node is mutably borrowed.
+    pub fn previous_sibling(&self) -> Option<SyncNode<T>> {
+        Some(SyncNode(
+            read(&self.0).previous_sibling.as_ref()?.upgrade()?,
+        ))
+    }
+
+    /// Returns the next sibling of this node, unless it is a last child.
+    ///
+    /// Blocks while the node is mutably borrowed.
+    pub fn next_sibling(&self) -> Option<SyncNode<T>> {
+        Some(SyncNode(read(&self.0).next_sibling.as_ref()?.clone()))
+    }
+
+    /// Returns a shared reference to this node's data.
+    ///
+    /// Blocks while the node is mutably borrowed.
+    pub fn borrow<'a>(&'a self) -> SyncRef<'a, T> {
+        SyncRef(read(&self.0))
+    }
+
+    /// Returns a unique/mutable reference to this node's data.
+    ///
+    /// Blocks while the node is borrowed.
+    pub fn borrow_mut<'a>(&'a self) -> SyncRefMut<'a, T> {
+        SyncRefMut(write(&self.0))
+    }
+
+    /// Returns an iterator of nodes to this node and its ancestors.
+    ///
+    /// Includes the current node.
+    pub fn ancestors(&self) -> Ancestors<T> {
+        Ancestors(Some(self.clone()))
+    }
+
+    /// Returns an iterator of nodes to this node and the siblings before it.
+    ///
+    /// Includes the current node.
+    pub fn preceding_siblings(&self) -> PrecedingSiblings<T> {
+        PrecedingSiblings(Some(self.clone()))
+    }
+
+    /// Returns an iterator of nodes to this node and the siblings after it.
+    ///
+    /// Includes the current node.
+    pub fn following_siblings(&self) -> FollowingSiblings<T> {
+        FollowingSiblings(Some(self.clone()))
+    }
+
+    /// Returns an iterator of nodes to this node's children.
+    pub fn children(&self) -> Children<T> {
+        Children {
+            next: self.first_child(),
+            next_back: self.last_child(),
+        }
+    }
+
+    /// Returns `true` if this node has children nodes.
+    pub fn has_children(&self) -> bool {
+        read(&self.0).first_child.is_some()
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order.
+    ///
+    /// Includes the current node.
+    pub fn descendants(&self) -> Descendants<T> {
+        Descendants(self.traverse())
+    }
+
+    /// Returns an iterator of nodes to this node and its descendants, in tree order.
+    pub fn traverse(&self) -> Traverse<T> {
+        Traverse {
+            root: self.clone(),
+            next: Some(NodeEdge::Start(self.clone())),
+            next_back: Some(NodeEdge::End(self.clone())),
+        }
+    }
+
+    /// Detaches a node from its parent and siblings. Children are not affected.
+    pub fn detach(&self) {
+        detach(&self.0);
+    }
+
+    // A debug-only check that an insertion will not create a reference cycle.
+    //
+    // Uses non-blocking locks and skips the check if one of the ancestors is
+    // locked, since the check must not introduce new deadlocks. Nodes without
+    // children are not checked, since they cannot be ancestors.
+    fn debug_assert_not_ancestor(&self, new_node: &SyncNode<T>) {
+        debug_assert!(
+            !new_node.has_children() || {
+                let mut is_ancestor = false;
+                let mut link = self.0.clone();
+                loop {
+                    let parent = match link.try_read() {
+                        Ok(data) => data.parent.as_ref().and_then(|weak| weak.upgrade()),
+                        Err(TryLockError::Poisoned(e)) => e
+                            .into_inner()
+                            .parent
+                            .as_ref()
+                            .and_then(|weak| weak.upgrade()),
+                        Err(TryLockError::WouldBlock) => None,
+                    };
+
+                    match parent {
+                        Some(parent) => {
+                            if Arc::ptr_eq(&parent, &new_node.0) {
+                                is_ancestor = true;
+                                break;
+                            }
+
+                            link = parent;
+                        }
+                        None => break,
+                    }
+                }
+
+                !is_ancestor
+            },
+            "a node cannot be inserted into its own subtree"
+        );
+    }
+
+    /// Appends a new child to this node, after existing children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new child is this node.
+    ///
+    /// In debug builds, also panics if the new child is an ancestor of this node.
+    pub fn append(&self, new_child: SyncNode<T>) {
+        assert!(*self != new_child, "a node cannot be appended to itself");
+        self.debug_assert_not_ancestor(&new_child);
+
+        new_child.detach();
+
+        let last_child_opt = {
+            let mut self_data = write(&self.0);
+            let last_child_opt = self_data.last_child.take().and_then(|weak| weak.upgrade());
+            self_data.last_child = Some(Arc::downgrade(&new_child.0));
+            if last_child_opt.is_none() {
+                debug_assert!(self_data.first_child.is_none());
+                self_data.first_child = Some(new_child.0.clone());
+            }
+
+            last_child_opt
+        };
+
+        {
+            let mut new_child_data = write(&new_child.0);
+            new_child_data.parent = Some(Arc::downgrade(&self.0));
+            new_child_data.previous_sibling = last_child_opt.as_ref().map(Arc::downgrade);
+        }
+
+        if let Some(last_child_strong) = last_child_opt {
+            let mut last_child_data = write(&last_child_strong);
+            debug_assert!(last_child_data.next_sibling.is_none());
+            last_child_data.next_sibling = Some(new_child.0);
+        }
+    }
+
+    /// Prepends a new child to this node, before existing children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new child is this node.
+    ///
+    /// In debug builds, also panics if the new child is an ancestor of this node.
+    pub fn prepend(&self, new_child: SyncNode<T>) {
+        assert!(*self != new_child, "a node cannot be prepended to itself");
+        self.debug_assert_not_ancestor(&new_child);
+
+        new_child.detach();
+
+        let first_child_opt = {
+            let mut self_data = write(&self.0);
+            let first_child_opt = self_data.first_child.take();
+            self_data.first_child = Some(new_child.0.clone());
+            if first_child_opt.is_none() {
+                self_data.last_child = Some(Arc::downgrade(&new_child.0));
+            }
+
+            first_child_opt
+        };
+
+        if let Some(ref first_child_strong) = first_child_opt {
+            let mut first_child_data = write(first_child_strong);
+            debug_assert!(first_child_data.previous_sibling.is_none());
+            first_child_data.previous_sibling = Some(Arc::downgrade(&new_child.0));
+        }
+
+        let mut new_child_data = write(&new_child.0);
+        new_child_data.parent = Some(Arc::downgrade(&self.0));
+        new_child_data.next_sibling = first_child_opt;
+    }
+
+    /// Inserts a new sibling after this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new sibling is this node.
+    ///
+    /// In debug builds, also panics if the new sibling is an ancestor of this node.
+    pub fn insert_after(&self, new_sibling: SyncNode<T>) {
+        assert!(
+            *self != new_sibling,
+            "a node cannot be inserted after itself"
+        );
+        self.debug_assert_not_ancestor(&new_sibling);
+
+        new_sibling.detach();
+
+        let (parent_weak, next_sibling_opt) = {
+            let mut self_data = write(&self.0);
+            let next_sibling_opt = self_data.next_sibling.take();
+            self_data.next_sibling = Some(new_sibling.0.clone());
+            (self_data.parent.clone(), next_sibling_opt)
+        };
+
+        match next_sibling_opt {
+            Some(ref next_sibling_strong) => {
+                let mut next_sibling_data = write(next_sibling_strong);
+                next_sibling_data.previous_sibling = Some(Arc::downgrade(&new_sibling.0));
+            }
+            None => {
+                if let Some(parent_strong) = parent_weak.as_ref().and_then(|weak| weak.upgrade()) {
+                    let mut parent_data = write(&parent_strong);
+                    parent_data.last_child = Some(Arc::downgrade(&new_sibling.0));
+                }
+            }
+        }
+
+        let mut new_sibling_data = write(&new_sibling.0);
+        new_sibling_data.parent = parent_weak;
+        new_sibling_data.previous_sibling = Some(Arc::downgrade(&self.0));
+        new_sibling_data.next_sibling = next_sibling_opt;
+    }
+
+    /// Inserts a new sibling before this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new sibling is this node.
+    ///
+    /// In debug builds, also panics if the new sibling is an ancestor of this node.
+    pub fn insert_before(&self, new_sibling: SyncNode<T>) {
+        assert!(
+            *self != new_sibling,
+            "a node cannot be inserted before itself"
+        );
+        self.debug_assert_not_ancestor(&new_sibling);
+
+        new_sibling.detach();
+
+        let (parent_weak, previous_sibling_opt) = {
+            let mut self_data = write(&self.0);
+            let previous_sibling_opt = self_data
+                .previous_sibling
+                .take()
+                .and_then(|weak| weak.upgrade());
+            self_data.previous_sibling = Some(Arc::downgrade(&new_sibling.0));
+            (self_data.parent.clone(), previous_sibling_opt)
+        };
+
+        match previous_sibling_opt {
+            Some(ref previous_sibling_strong) => {
+                let mut previous_sibling_data = write(previous_sibling_strong);
+                previous_sibling_data.next_sibling = Some(new_sibling.0.clone());
+            }
+            None => {
+                if let Some(parent_strong) = parent_weak.as_ref().and_then(|weak| weak.upgrade()) {
+                    let mut parent_data = write(&parent_strong);
+                    parent_data.first_child = Some(new_sibling.0.clone());
+                }
+            }
+        }
+
+        let mut new_sibling_data = write(&new_sibling.0);
+        new_sibling_data.parent = parent_weak;
+        new_sibling_data.previous_sibling = previous_sibling_opt.as_ref().map(Arc::downgrade);
+        new_sibling_data.next_sibling = Some(self.0.clone());
+    }
+
+    /// Returns a copy of a current node without children.
+    pub fn make_copy(&self) -> SyncNode<T>
+    where
+        T: Clone,
+    {
+        SyncNode::new(self.borrow().clone())
+    }
+
+    /// Returns a copy of a current node with children.
+    pub fn make_deep_copy(&self) -> SyncNode<T>
+    where
+        T: Clone,
+    {
+        let root = self.make_copy();
+
+        // Use an explicit stack of new parents to prevent a stack overflow on deep trees.
+        let mut stack = vec![root.clone()];
+        for edge in self.traverse().skip(1) {
+            match edge {
+                NodeEdge::Start(node) => {
+                    let new_node = node.make_copy();
+                    if let Some(parent) = stack.last() {
+                        parent.append(new_node.clone());
+                    }
+                    stack.push(new_node);
+                }
+                NodeEdge::End(_) => {
+                    stack.pop();
+                }
+            }
+        }
+
+        root
+    }
+}
+
+/// Cloning a `SyncWeakNode` only increments a reference count. It does not copy the data.
+impl<T> Clone for SyncWeakNode<T> {
+    fn clone(&self) -> Self {
+        SyncWeakNode(Weak::clone(&self.0))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SyncWeakNode<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("(SyncWeakNode)")
+    }
+}
+
+impl<T> SyncWeakNode<T> {
+    /// Attempts to upgrade the SyncWeakNode to a SyncNode.
+    pub fn upgrade(&self) -> Option<SyncNode<T>> {
+        self.0.upgrade().map(SyncNode)
+    }
+}
+
+impl<T> NodeData<T> {
+    // Unlinks a node from its parent and siblings, without updating them.
+    fn take_links(&mut self) -> Links<T> {
+        (
+            self.parent.take(),
+            self.previous_sibling.take(),
+            self.next_sibling.take(),
+        )
+    }
+}
+
+// Detaches a node from its parent and siblings. Children are not affected.
+fn detach<T>(link: &Link<T>) {
+    let links = write(link).take_links();
+    relink(links);
+}
+
+// Links the former neighbors of a detached node to each other.
+fn relink<T>(links: Links<T>) {
+    let (parent_weak, previous_sibling_weak, next_sibling_strong) = links;
+
+    let previous_sibling_opt = previous_sibling_weak
+        .as_ref()
+        .and_then(|weak| weak.upgrade());
+
+    if let Some(next_sibling_ref) = next_sibling_strong.as_ref() {
+        let mut next_sibling_data = write(next_sibling_ref);
+        next_sibling_data.previous_sibling = previous_sibling_weak;
+    } else if let Some(parent_ref) = parent_weak.as_ref() {
+        if let Some(parent_strong) = parent_ref.upgrade() {
+            let mut parent_data = write(&parent_strong);
+            parent_data.last_child = previous_sibling_weak;
+        }
+    }
+
+    if let Some(previous_sibling_strong) = previous_sibling_opt {
+        let mut previous_sibling_data = write(&previous_sibling_strong);
+        previous_sibling_data.next_sibling = next_sibling_strong;
+    } else if let Some(parent_ref) = parent_weak.as_ref() {
+        if let Some(parent_strong) = parent_ref.upgrade() {
+            let mut parent_data = write(&parent_strong);
+            parent_data.first_child = next_sibling_strong;
+        }
+    }
+}
+
+impl<T> Drop for NodeData<T> {
+    fn drop(&mut self) {
+        // Detach all descendant nodes recursively to prevent a stack overflow.
+        if let Some(child) = self.first_child.take() {
+            let mut open_set = vec![child];
+
+            while let Some(node) = open_set.pop() {
+                let links = {
+                    let mut node_data = write(&node);
+                    if let Some(next_sibling) = node_data.next_sibling.as_ref() {
+                        open_set.push(next_sibling.clone());
+                    }
+
+                    // Child nodes should be processed if and only if strong_count is one,
+                    // which means self is the only reference. Otherwise keep the subtree unchanged.
+                    if Arc::strong_count(&node) == 1 {
+                        if let Some(first_child) = node_data.first_child.as_ref() {
+                            open_set.push(first_child.clone());
+                        }
+                    }
+
+                    node_data.take_links()
+                };
+
+                relink(links);
+            }
+        }
+    }
+}
+
+/// An iterator of nodes to the ancestors a given node.
+pub struct Ancestors<T>(Option<SyncNode<T>>);
+
+impl<T> Iterator for Ancestors<T> {
+    type Item = SyncNode<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        self.0 = node.parent();
+        Some(node)
+    }
+}
+
+/// An iterator of nodes to the siblings before a given node.
+pub struct PrecedingSiblings<T>(Option<SyncNode<T>>);
+
+impl<T> Iterator for PrecedingSiblings<T> {
+    type Item = SyncNode<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        self.0 = node.previous_sibling();
+        Some(node)
+    }
+}
+
+/// An iterator of nodes to the siblings after a given node.
+pub struct FollowingSiblings<T>(Option<SyncNode<T>>);
+
+impl<T> Iterator for FollowingSiblings<T> {
+    type Item = SyncNode<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.0.take()?;
+        self.0 = node.next_sibling();
+        Some(node)
+    }
+}
+
+/// A double ended iterator of nodes to the children of a given node.
+pub struct Children<T> {
+    next: Option<SyncNode<T>>,
+    next_back: Option<SyncNode<T>>,
+}
+
+impl<T> Children<T> {
+    // true if self.next_back's next sibling is self.next
+    fn finished(&self) -> bool {
+        match self.next_back {
+            Some(ref next_back) => next_back.next_sibling() == self.next,
+            _ => true,
+        }
+    }
+}
+
+impl<T> Iterator for Children<T> {
+    type Item = SyncNode<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let node = self.next.take()?;
+        self.next = node.next_sibling();
+        Some(node)
+    }
+}
+
+impl<T> DoubleEndedIterator for Children<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let node = self.next_back.take()?;
+        self.next_back = node.previous_sibling();
+        Some(node)
+    }
+}
+
+/// An iterator of nodes to a given node and its descendants, in tree order.
+pub struct Descendants<T>(Traverse<T>);
+
+impl<T> Iterator for Descendants<T> {
+    type Item = SyncNode<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next() {
+                Some(NodeEdge::Start(node)) => return Some(node),
+                Some(NodeEdge::End(_)) => {}
+                None => return None,
+            }
+        }
+    }
+}
+
+/// A node type during traverse.
+#[derive(Clone, Debug)]
+pub enum NodeEdge<T> {
+    /// Indicates that start of a node that has children.
+    /// Yielded by `Traverse::next` before the node's descendants.
+    /// In HTML or XML, this corresponds to an opening tag like `<div>`
+    Start(SyncNode<T>),
+
+    /// Indicates that end of a node that has children.
+    /// Yielded by `Traverse::next` after the node's descendants.
+    /// In HTML or XML, this corresponds to a closing tag like `</div>`
+    End(SyncNode<T>),
+}
+
+// Implement PartialEq manually, because we do not need to require T: PartialEq
+impl<T> PartialEq for NodeEdge<T> {
+    fn eq(&self, other: &NodeEdge<T>) -> bool {
+        match *self {
+            NodeEdge::Start(ref n1) => match *other {
+                NodeEdge::Start(ref n2) => *n1 == *n2,
+                NodeEdge::End(_) => false,
+            },
+            NodeEdge::End(ref n1) => match *other {
+                NodeEdge::End(ref n2) => *n1 == *n2,
+                NodeEdge::Start(_) => false,
+            },
+        }
+    }
+}
+
+impl<T> NodeEdge<T> {
+    fn next_edge(&self, root: &SyncNode<T>) -> Option<NodeEdge<T>> {
+        match *self {
+            NodeEdge::Start(ref node) => match node.first_child() {
+                Some(first_child) => Some(NodeEdge::Start(first_child)),
+                None => Some(NodeEdge::End(node.clone())),
+            },
+            NodeEdge::End(ref node) => {
+                if *node == *root {
+                    None
+                } else {
+                    match node.next_sibling() {
+                        Some(next_sibling) => Some(NodeEdge::Start(next_sibling)),
+                        // `node.parent()` here can only be `None`
+                        // if the tree has been modified during iteration,
+                        // but silently stopping iteration
+                        // seems a more sensible behavior than panicking.
+                        None => node.parent().map(NodeEdge::End),
+                    }
+                }
+            }
+        }
+    }
+
+    fn previous_edge(&self, root: &SyncNode<T>) -> Option<NodeEdge<T>> {
+        match *self {
+            NodeEdge::End(ref node) => match node.last_child() {
+                Some(last_child) => Some(NodeEdge::End(last_child)),
+                None => Some(NodeEdge::Start(node.clone())),
+            },
+            NodeEdge::Start(ref node) => {
+                if *node == *root {
+                    None
+                } else {
+                    match node.previous_sibling() {
+                        Some(previous_sibling) => Some(NodeEdge::End(previous_sibling)),
+                        // `node.parent()` here can only be `None`
+                        // if the tree has been modified during iteration,
+                        // but silently stopping iteration
+                        // seems a more sensible behavior than panicking.
+                        None => node.parent().map(NodeEdge::Start),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A double ended iterator of nodes to a given node and its descendants,
+/// in tree order.
+pub struct Traverse<T> {
+    root: SyncNode<T>,
+    next: Option<NodeEdge<T>>,
+    next_back: Option<NodeEdge<T>>,
+}
+
+impl<T> Traverse<T> {
+    // true if self.next_back's next edge is self.next
+    fn finished(&self) -> bool {
+        match self.next_back {
+            Some(ref next_back) => next_back.next_edge(&self.root) == self.next,
+            _ => true,
+        }
+    }
+}
+
+impl<T> Iterator for Traverse<T> {
+    type Item = NodeEdge<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let node = self.next.take()?;
+        self.next = node.next_edge(&self.root);
+        Some(node)
+    }
+}
+
+impl<T> DoubleEndedIterator for Traverse<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.finished() {
+            return None;
+        }
+
+        let node = self.next_back.take()?;
+        self.next_back = node.previous_edge(&self.root);
+        Some(node)
+    }
+}
@@ -0,0 +1,192 @@
+use std::mem;
+use std::thread;
+
+use super::{Node, WeakNode};
+
+/// A group of tree mutations that can be rolled back.
+///
+/// Each operation records the previous position or data of the affected node,
+/// so rolling back does not need to copy the tree.
+/// Previous positions are stored as weak references to the old parent and siblings.
+/// The affected nodes themselves are kept alive by the transaction,
+/// so the old neighbors are alive too as long as the tree is changed
+/// only through the transaction.
+///
+/// A transaction that is dropped without calling `commit` is rolled back,
+/// unless the thread is panicking.
+pub struct Transaction<T> {
+    log: Vec<Undo<T>>,
+}
+
+/// A position in a transaction, created by `Transaction::savepoint`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Savepoint(usize);
+
+enum Undo<T> {
+    Position {
+        node: Node<T>,
+        parent: Option<WeakNode<T>>,
+        previous_sibling: Option<WeakNode<T>>,
+        next_sibling: Option<WeakNode<T>>,
+    },
+    Data {
+        node: Node<T>,
+        data: T,
+    },
+}
+
+impl<T> Transaction<T> {
+    /// Starts a new transaction.
+    pub fn new() -> Transaction<T> {
+        Transaction { log: Vec::new() }
+    }
+
+    /// Like `Node::append`, but can be rolled back.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::append`.
+    pub fn append(&mut self, parent: &Node<T>, new_child: Node<T>) {
+        self.record_position(&new_child);
+        parent.append(new_child);
+    }
+
+    /// Like `Node::prepend`, but can be rolled back.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::prepend`.
+    pub fn prepend(&mut self, parent: &Node<T>, new_child: Node<T>) {
+        self.record_position(&new_child);
+        parent.prepend(new_child);
+    }
+
+    /// Like `Node::insert_after`, but can be rolled back.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::insert_after`.
+    pub fn insert_after(&mut self, node: &Node<T>, new_sibling: Node<T>) {
+        self.record_position(&new_sibling);
+        node.insert_after(new_sibling);
+    }
+
+    /// Like `Node::insert_before`, but can be rolled back.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::insert_before`.
+    pub fn insert_before(&mut self, node: &Node<T>, new_sibling: Node<T>) {
+        self.record_position(&new_sibling);
+        node.insert_before(new_sibling);
+    }
+
+    /// Like `Node::detach`, but can be rolled back.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as `Node::detach`.
+    pub fn detach(&mut self, node: &Node<T>) {
+        self.record_position(node);
+        node.detach();
+    }
+
+    /// Replaces the data of a node, keeping the old data to be restored on rollback.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node is currently borrowed.
+    pub fn replace_data(&mut self, node: &Node<T>, data: T) {
+        let old = mem::replace(&mut *node.borrow_mut(), data);
+        self.log.push(Undo::Data {
+            node: node.clone(),
+            data: old,
+        });
+    }
+
+    /// Returns the current position in the transaction.
+    ///
+    /// Savepoints can be nested: rolling back to a savepoint
+    /// also discards all the savepoints created after it.
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint(self.log.len())
+    }
+
+    /// Rolls back the operations made after the savepoint.
+    ///
+    /// Does nothing if the operations have already been rolled back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the affected nodes is currently borrowed.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) {
+        while self.log.len() > savepoint.0 {
+            self.undo_last();
+        }
+    }
+
+    /// Keeps all the changes.
+    pub fn commit(mut self) {
+        self.log.clear();
+    }
+
+    /// Rolls back all the operations in reverse order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the affected nodes is currently borrowed.
+    pub fn rollback(mut self) {
+        self.rollback_to(Savepoint(0));
+    }
+
+    fn record_position(&mut self, node: &Node<T>) {
+        self.log.push(Undo::Position {
+            node: node.clone(),
+            parent: node.parent().map(|node| node.downgrade()),
+            previous_sibling: node.previous_sibling().map(|node| node.downgrade()),
+            next_sibling: node.next_sibling().map(|node| node.downgrade()),
+        });
+    }
+
+    fn undo_last(&mut self) {
+        match self.log.pop() {
+            Some(Undo::Position {
+                node,
+                parent,
+                previous_sibling,
+                next_sibling,
+            }) => {
+                let upgrade = |weak: Option<WeakNode<T>>| weak.and_then(|weak| weak.upgrade());
+                match (
+                    upgrade(previous_sibling),
+                    upgrade(parent),
+                    upgrade(next_sibling),
+                ) {
+                    (Some(previous_sibling), _, _) => previous_sibling.insert_after(node),
+                    (None, Some(parent), _) => parent.prepend(node),
+                    (None, None, Some(next_sibling)) => next_sibling.insert_before(node),
+                    (None, None, None) => node.detach(),
+                }
+            }
+            Some(Undo::Data { node, data }) => {
+                *node.borrow_mut() = data;
+            }
+            None => {}
+        }
+    }
+}
+
+impl<T> Default for Transaction<T> {
+    fn default() -> Self {
+        Transaction::new()
+    }
+}
+
+impl<T> Drop for Transaction<T> {
+    fn drop(&mut self) {
+        // Rolling back can panic, which would abort the process while unwinding.
+        if !thread::panicking() {
+            self.rollback_to(Savepoint(0));
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use super::{Node, NodeEdge, Visit};
+
+/// The result of `Visitor::enter`.
+pub type VisitResult<E> = Result<Visit, E>;
+
+/// A callback interface for `Node::accept`.
+pub trait Visitor<T> {
+    /// The error that aborts the traversal.
+    type Error;
+
+    /// Called when a node is entered, before its descendants.
+    ///
+    /// Returning an error aborts the traversal.
+    fn enter(&mut self, node: &Node<T>) -> VisitResult<Self::Error> {
+        let _ = node;
+        Ok(Visit::Continue)
+    }
+
+    /// Called when a node is left, after its descendants.
+    ///
+    /// Not called if the traversal was stopped or aborted inside the node.
+    /// Returning an error aborts the traversal.
+    fn leave(&mut self, node: &Node<T>) -> Result<(), Self::Error> {
+        let _ = node;
+        Ok(())
+    }
+}
+
+impl<T> Node<T> {
+    /// Walks this node and its descendants in tree order, calling the visitor on each node.
+    ///
+    /// Uses `Traverse`, so there is no recursion regardless of the tree depth.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by the visitor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn accept<V: Visitor<T>>(&self, visitor: &mut V) -> Result<(), V::Error> {
+        let mut traverse = self.traverse();
+        while let Some(edge) = traverse.next() {
+            match edge {
+                NodeEdge::Start(node) => match visitor.enter(&node)? {
+                    Visit::Continue => {}
+                    Visit::SkipChildren => traverse.skip_subtree(),
+                    Visit::Stop => break,
+                },
+                NodeEdge::End(node) => visitor.leave(&node)?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1211 @@
+/*!
+XPath queries.
+
+`select` evaluates an XPath 1.0 location path against a tree
+which data implements `XPathData`, and returns the selected nodes in tree order.
+
+The root of the tree is used as the root node of the document,
+so `/item` selects the children of the root named `item`.
+
+Supported are:
+
+* all the axes except `namespace`, along with the abbreviated `@`, `.`, `..` and `//` forms,
+* the name, `*`, `node()` and `text()` node tests,
+* predicates, including positional ones,
+* the `or`, `and`, `=`, `!=`, `<`, `<=`, `>`, `>=`, `+`, `-`, `*`, `div` and `mod` operators,
+* string and number literals, and parenthesized expressions, such as `(//item)[1]`,
+* the `last`, `position`, `count`, `name`, `not`, `true`, `false`, `boolean`, `number`,
+  `string`, `concat`, `contains`, `starts-with`, `string-length` and `normalize-space` functions.
+
+Attributes are not nodes, so the `attribute` axis is only allowed as the last step
+of a path inside an expression, as in `item[@id = 'x']`, and without a wildcard.
+Unions, variables and namespaces are not supported.
+*/
+
+use std::error;
+use std::f64;
+use std::fmt;
+use std::str::FromStr;
+
+use super::Node;
+
+/// The node data that XPath expressions can query.
+pub trait XPathData {
+    /// Returns the name of an element node, or `None` for other nodes.
+    fn name(&self) -> Option<&str>;
+
+    /// Returns the value of an attribute of an element node.
+    fn attribute(&self, name: &str) -> Option<&str>;
+
+    /// Returns the text of a text node, or `None` for other nodes.
+    fn text(&self) -> Option<&str>;
+}
+
+/// An error returned when parsing an XPath expression fails.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// The byte offset of the error in the expression.
+    pub position: usize,
+    message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        "invalid XPath expression"
+    }
+}
+
+/// A parsed XPath location path, which can be evaluated many times.
+#[derive(Clone, Debug)]
+pub struct XPath(Path);
+
+impl XPath {
+    /// Parses a location path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `expr` is not a valid or supported location path.
+    pub fn parse(expr: &str) -> Result<XPath, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(expr)?,
+            index: 0,
+            end: expr.len(),
+        };
+
+        let parsed = parser.parse_expr()?;
+        if parser.index != parser.tokens.len() {
+            return Err(parser.error("unexpected token"));
+        }
+
+        match parsed {
+            Expr::Path(ref path) if path.attribute.is_some() => Err(ParseError {
+                position: 0,
+                message: "attributes cannot be selected",
+            }),
+            Expr::Path(path) => Ok(XPath(path)),
+            _ => Err(ParseError {
+                position: 0,
+                message: "expression does not select nodes",
+            }),
+        }
+    }
+
+    /// Returns the nodes selected by the path, using `node` as the context node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the visited nodes is currently mutably borrowed.
+    pub fn select<T: XPathData>(&self, node: &Node<T>) -> Vec<Node<T>> {
+        let context = Context {
+            node,
+            position: 1,
+            size: 1,
+        };
+        select_path(&self.0, &context)
+    }
+}
+
+impl FromStr for XPath {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        XPath::parse(s)
+    }
+}
+
+/// Parses a location path and returns the nodes it selects, using `node` as the context node.
+///
+/// # Errors
+///
+/// Returns an error if `expr` is not a valid or supported location path.
+///
+/// # Panics
+///
+/// Panics if one of the visited nodes is currently mutably borrowed.
+pub fn select<T: XPathData>(node: &Node<T>, expr: &str) -> Result<Vec<Node<T>>, ParseError> {
+    Ok(XPath::parse(expr)?.select(node))
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Slash,
+    DoubleSlash,
+    LeftBracket,
+    RightBracket,
+    LeftParen,
+    RightParen,
+    At,
+    Comma,
+    Dot,
+    DoubleDot,
+    DoubleColon,
+    Pipe,
+    Dollar,
+    Star,
+    Name(String),
+    Literal(String),
+    Number(f64),
+    Operator(Operator),
+}
+
+impl Token {
+    fn name(&self) -> Option<&str> {
+        match *self {
+            Token::Name(ref name) => Some(name),
+            _ => None,
+        }
+    }
+
+    // Returns `true` if the token can be the last one of an operand.
+    fn ends_operand(&self) -> bool {
+        match *self {
+            Token::Name(_) | Token::Literal(_) | Token::Number(_) => true,
+            ref token => [
+                Token::RightBracket,
+                Token::RightParen,
+                Token::Dot,
+                Token::DoubleDot,
+                Token::Star,
+            ]
+            .contains(token),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Plus,
+    Minus,
+    Multiply,
+    Div,
+    Mod,
+}
+
+impl Operator {
+    // Returns the operator that gives the same result with swapped operands.
+    fn flipped(self) -> Operator {
+        match self {
+            Operator::Less => Operator::Greater,
+            Operator::LessOrEqual => Operator::GreaterOrEqual,
+            Operator::Greater => Operator::Less,
+            Operator::GreaterOrEqual => Operator::LessOrEqual,
+            op => op,
+        }
+    }
+}
+
+// Binary operators from the lowest to the highest precedence.
+const PRECEDENCE: &[&[Operator]] = &[
+    &[Operator::Or],
+    &[Operator::And],
+    &[Operator::Equal, Operator::NotEqual],
+    &[
+        Operator::Less,
+        Operator::LessOrEqual,
+        Operator::Greater,
+        Operator::GreaterOrEqual,
+    ],
+    &[Operator::Plus, Operator::Minus],
+    &[Operator::Multiply, Operator::Div, Operator::Mod],
+];
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c as u32 > 0x7f
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start(c) || is_digit(c) || c == '-' || c == '.'
+}
+
+fn is_digit(c: char) -> bool {
+    "0123456789".contains(c)
+}
+
+fn tokenize(s: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = s[pos..].chars().next() {
+        let start = pos;
+        let rest = &s[pos..];
+        let next = rest[c.len_utf8()..].chars().next();
+        pos += c.len_utf8();
+
+        // `*` and operator names are operators only after a token that ends an operand.
+        let after_operand = match tokens.last() {
+            Some(token) => token.0.ends_operand(),
+            None => false,
+        };
+
+        let error = |message| ParseError {
+            position: start,
+            message,
+        };
+
+        let token = match c {
+            ' ' | '\t' | '\r' | '\n' => continue,
+            '/' if next == Some('/') => {
+                pos += 1;
+                Token::DoubleSlash
+            }
+            '/' => Token::Slash,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '@' => Token::At,
+            ',' => Token::Comma,
+            '|' => Token::Pipe,
+            '$' => Token::Dollar,
+            '.' if next == Some('.') => {
+                pos += 1;
+                Token::DoubleDot
+            }
+            ':' if next == Some(':') => {
+                pos += 1;
+                Token::DoubleColon
+            }
+            '*' if after_operand => Token::Operator(Operator::Multiply),
+            '*' => Token::Star,
+            '=' => Token::Operator(Operator::Equal),
+            '!' if next == Some('=') => {
+                pos += 1;
+                Token::Operator(Operator::NotEqual)
+            }
+            '<' if next == Some('=') => {
+                pos += 1;
+                Token::Operator(Operator::LessOrEqual)
+            }
+            '<' => Token::Operator(Operator::Less),
+            '>' if next == Some('=') => {
+                pos += 1;
+                Token::Operator(Operator::GreaterOrEqual)
+            }
+            '>' => Token::Operator(Operator::Greater),
+            '+' => Token::Operator(Operator::Plus),
+            '-' => Token::Operator(Operator::Minus),
+            '\'' | '"' => match rest[1..].find(c) {
+                Some(len) => {
+                    pos += len + 1;
+                    Token::Literal(rest[1..len + 1].to_string())
+                }
+                None => return Err(error("unterminated literal")),
+            },
+            c if c == '.' || is_digit(c) => {
+                let len = rest
+                    .find(|c| !is_digit(c) && c != '.')
+                    .unwrap_or(rest.len());
+                if len == 1 && c == '.' {
+                    Token::Dot
+                } else {
+                    pos = start + len;
+                    match rest[..len].parse() {
+                        Ok(number) => Token::Number(number),
+                        Err(_) => return Err(error("invalid number")),
+                    }
+                }
+            }
+            c if is_name_start(c) => {
+                let mut len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+
+                // A qualified name, but not an axis name followed by `::`.
+                let mut after = rest[len..].chars();
+                if after.next() == Some(':') && after.next().into_iter().any(is_name_start) {
+                    len += 1;
+                    len += rest[len..]
+                        .find(|c| !is_name_char(c))
+                        .unwrap_or(rest.len() - len);
+                }
+
+                pos = start + len;
+                let name = &rest[..len];
+                match name {
+                    "or" if after_operand => Token::Operator(Operator::Or),
+                    "and" if after_operand => Token::Operator(Operator::And),
+                    "div" if after_operand => Token::Operator(Operator::Div),
+                    "mod" if after_operand => Token::Operator(Operator::Mod),
+                    _ => Token::Name(name.to_string()),
+                }
+            }
+            _ => return Err(error("unexpected character")),
+        };
+
+        tokens.push((token, start));
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(String),
+    Number(f64),
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Function(Function, Vec<Expr>),
+    Path(Path),
+}
+
+impl Expr {
+    fn is_node_set(&self) -> bool {
+        match *self {
+            Expr::Path(ref path) => path.attribute.is_none(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Function {
+    Last,
+    Position,
+    Count,
+    Name,
+    Not,
+    True,
+    False,
+    Boolean,
+    Number,
+    String,
+    Concat,
+    Contains,
+    StartsWith,
+    StringLength,
+    NormalizeSpace,
+}
+
+impl Function {
+    // Returns the function with the minimum and the maximum number of arguments, if any.
+    fn find(name: &str) -> Option<(Function, usize, Option<usize>)> {
+        let function = match name {
+            "last" => (Function::Last, 0, Some(0)),
+            "position" => (Function::Position, 0, Some(0)),
+            "count" => (Function::Count, 1, Some(1)),
+            "name" => (Function::Name, 0, Some(1)),
+            "not" => (Function::Not, 1, Some(1)),
+            "true" => (Function::True, 0, Some(0)),
+            "false" => (Function::False, 0, Some(0)),
+            "boolean" => (Function::Boolean, 1, Some(1)),
+            "number" => (Function::Number, 0, Some(1)),
+            "string" => (Function::String, 0, Some(1)),
+            "concat" => (Function::Concat, 2, None),
+            "contains" => (Function::Contains, 2, Some(2)),
+            "starts-with" => (Function::StartsWith, 2, Some(2)),
+            "string-length" => (Function::StringLength, 0, Some(1)),
+            "normalize-space" => (Function::NormalizeSpace, 0, Some(1)),
+            _ => return None,
+        };
+
+        Some(function)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Path {
+    start: Start,
+    steps: Vec<Step>,
+    // The name of the attribute selected by the last step.
+    attribute: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+enum Start {
+    Context,
+    Root,
+    // A node set expression with predicates.
+    Filter(Box<Expr>, Vec<Expr>),
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+impl Step {
+    fn descendant_or_self() -> Step {
+        Step {
+            axis: Axis::DescendantOrSelf,
+            test: NodeTest::Node,
+            predicates: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    Itself,
+}
+
+impl Axis {
+    fn find(name: &str) -> Option<Axis> {
+        let axis = match name {
+            "ancestor" => Axis::Ancestor,
+            "ancestor-or-self" => Axis::AncestorOrSelf,
+            "child" => Axis::Child,
+            "descendant" => Axis::Descendant,
+            "descendant-or-self" => Axis::DescendantOrSelf,
+            "following" => Axis::Following,
+            "following-sibling" => Axis::FollowingSibling,
+            "parent" => Axis::Parent,
+            "preceding" => Axis::Preceding,
+            "preceding-sibling" => Axis::PrecedingSibling,
+            "self" => Axis::Itself,
+            _ => return None,
+        };
+
+        Some(axis)
+    }
+
+    fn is_reverse(self) -> bool {
+        [
+            Axis::Ancestor,
+            Axis::AncestorOrSelf,
+            Axis::Preceding,
+            Axis::PrecedingSibling,
+        ]
+        .contains(&self)
+    }
+
+    // Returns the nodes on the axis that pass the test, in the axis order.
+    fn select<T: XPathData>(self, node: &Node<T>, test: &NodeTest) -> Vec<Node<T>> {
+        match self {
+            Axis::Ancestor => test.filter(node.ancestors().skip(1)),
+            Axis::AncestorOrSelf => test.filter(node.ancestors()),
+            Axis::Child => test.filter(node.children()),
+            Axis::Descendant => test.filter(node.descendants().skip(1)),
+            Axis::DescendantOrSelf => test.filter(node.descendants()),
+            Axis::Following => test.filter(node.following()),
+            Axis::FollowingSibling => test.filter(node.following_siblings().skip(1)),
+            Axis::Parent => test.filter(node.parent().into_iter()),
+            Axis::Preceding => test.filter(node.preceding()),
+            Axis::PrecedingSibling => test.filter(node.preceding_siblings().skip(1)),
+            Axis::Itself => test.filter(Some(node.clone()).into_iter()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum NodeTest {
+    Name(String),
+    Element,
+    Text,
+    Node,
+}
+
+impl NodeTest {
+    fn filter<T, I>(&self, nodes: I) -> Vec<Node<T>>
+    where
+        T: XPathData,
+        I: Iterator<Item = Node<T>>,
+    {
+        nodes
+            .filter(|node| {
+                let data = node.borrow();
+                match *self {
+                    NodeTest::Name(ref name) => data.name() == Some(name.as_str()),
+                    NodeTest::Element => data.name().is_some(),
+                    NodeTest::Text => data.text().is_some(),
+                    NodeTest::Node => true,
+                }
+            })
+            .collect()
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.index + offset).map(|pair| &pair.0)
+    }
+
+    fn error(&self, message: &'static str) -> ParseError {
+        match self.tokens.get(self.index) {
+            Some(&(_, position)) => ParseError { position, message },
+            None => ParseError {
+                position: self.end,
+                message: "unexpected end of expression",
+            },
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error("unexpected token"))
+        }
+    }
+
+    fn next_name(&mut self) -> Result<String, ParseError> {
+        let name = match self.peek().and_then(Token::name) {
+            Some(name) => name.to_string(),
+            None => return Err(self.error("expected a name")),
+        };
+
+        self.index += 1;
+        Ok(name)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        self.parse_binary(0)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+
+        let mut left = self.parse_binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(&Token::Operator(op)) if PRECEDENCE[level].contains(&op) => op,
+                _ => return Ok(left),
+            };
+
+            self.index += 1;
+            let right = self.parse_binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat(&Token::Operator(Operator::Minus)) {
+            return Ok(Expr::Negate(Box::new(self.parse_unary()?)));
+        }
+
+        let expr = self.parse_path()?;
+        if self.peek() == Some(&Token::Pipe) {
+            return Err(self.error("unions are not supported"));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_path(&mut self) -> Result<Expr, ParseError> {
+        let start = match self.peek() {
+            Some(&Token::Slash) => {
+                self.index += 1;
+                let mut path = Path::new(Start::Root);
+                if self.at_step() {
+                    self.parse_steps(&mut path)?;
+                }
+
+                return Ok(Expr::Path(path));
+            }
+            Some(&Token::DoubleSlash) => Start::Root,
+            _ if self.at_primary() => {
+                let position = self.tokens[self.index].1;
+                let primary = self.parse_primary()?;
+                let predicates = self.parse_predicates()?;
+                let is_path =
+                    self.peek() == Some(&Token::Slash) || self.peek() == Some(&Token::DoubleSlash);
+
+                if predicates.is_empty() && !is_path {
+                    return Ok(primary);
+                }
+
+                if !primary.is_node_set() {
+                    return Err(ParseError {
+                        position,
+                        message: "expected a node set",
+                    });
+                }
+
+                let mut path = Path::new(Start::Filter(Box::new(primary), predicates));
+                if is_path {
+                    self.parse_separator(&mut path);
+                    self.parse_steps(&mut path)?;
+                }
+
+                return Ok(Expr::Path(path));
+            }
+            _ => Start::Context,
+        };
+
+        let mut path = Path::new(start);
+        self.parse_separator(&mut path);
+        self.parse_steps(&mut path)?;
+        Ok(Expr::Path(path))
+    }
+
+    // Consumes `/` or `//` before a step.
+    fn parse_separator(&mut self, path: &mut Path) {
+        if self.eat(&Token::DoubleSlash) {
+            path.steps.push(Step::descendant_or_self());
+        } else {
+            self.eat(&Token::Slash);
+        }
+    }
+
+    fn parse_steps(&mut self, path: &mut Path) -> Result<(), ParseError> {
+        loop {
+            self.parse_step(path)?;
+            match self.peek() {
+                Some(&Token::Slash) | Some(&Token::DoubleSlash) => {
+                    if path.attribute.is_some() {
+                        return Err(self.error("attributes have no children"));
+                    }
+
+                    self.parse_separator(path);
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn at_step(&self) -> bool {
+        match self.peek() {
+            Some(&Token::Dot) | Some(&Token::DoubleDot) | Some(&Token::At) | Some(&Token::Star) => {
+                true
+            }
+            Some(&Token::Name(_)) => !self.at_primary(),
+            _ => false,
+        }
+    }
+
+    fn at_primary(&self) -> bool {
+        match self.peek() {
+            Some(&Token::Literal(_))
+            | Some(&Token::Number(_))
+            | Some(&Token::LeftParen)
+            | Some(&Token::Dollar) => true,
+            Some(token) => match token.name() {
+                // A function call.
+                Some(name) => self.peek_at(1) == Some(&Token::LeftParen) && !is_node_type(name),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let position = self.tokens[self.index].1;
+        let token = self.tokens[self.index].0.clone();
+        self.index += 1;
+        match token {
+            Token::Literal(s) => Ok(Expr::Literal(s)),
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::LeftParen => {
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RightParen)?;
+                Ok(expr)
+            }
+            Token::Name(name) => {
+                let (function, min, max) = Function::find(&name).ok_or(ParseError {
+                    position,
+                    message: "unknown function",
+                })?;
+
+                self.expect(&Token::LeftParen)?;
+                let mut args = Vec::new();
+                if !self.eat(&Token::RightParen) {
+                    loop {
+                        args.push(self.parse_expr()?);
+                        if self.eat(&Token::RightParen) {
+                            break;
+                        }
+
+                        self.expect(&Token::Comma)?;
+                    }
+                }
+
+                let error = |message| Err(ParseError { position, message });
+
+                if args.len() < min || args.len() > max.unwrap_or(args.len()) {
+                    return error("wrong number of arguments");
+                }
+
+                match (function, args.first()) {
+                    (Function::Count, Some(&Expr::Path(_))) => {}
+                    (Function::Count, _) => return error("expected a path argument"),
+                    (Function::Name, Some(arg)) if !arg.is_node_set() => {
+                        return error("expected a node set argument");
+                    }
+                    _ => {}
+                }
+
+                Ok(Expr::Function(function, args))
+            }
+            _ => Err(ParseError {
+                position,
+                message: "variables are not supported",
+            }),
+        }
+    }
+
+    fn parse_predicates(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut predicates = Vec::new();
+        while self.eat(&Token::LeftBracket) {
+            predicates.push(self.parse_expr()?);
+            self.expect(&Token::RightBracket)?;
+        }
+
+        Ok(predicates)
+    }
+
+    fn parse_step(&mut self, path: &mut Path) -> Result<(), ParseError> {
+        if self.eat(&Token::Dot) {
+            path.steps.push(Step {
+                axis: Axis::Itself,
+                test: NodeTest::Node,
+                predicates: Vec::new(),
+            });
+            return Ok(());
+        }
+
+        if self.eat(&Token::DoubleDot) {
+            path.steps.push(Step {
+                axis: Axis::Parent,
+                test: NodeTest::Node,
+                predicates: Vec::new(),
+            });
+            return Ok(());
+        }
+
+        let mut axis = Axis::Child;
+        if self.eat(&Token::At) {
+            return self.parse_attribute(path);
+        } else if self.peek_at(1) == Some(&Token::DoubleColon) {
+            let name = self.next_name()?;
+            self.index += 1;
+            axis = match name.as_str() {
+                "attribute" => return self.parse_attribute(path),
+                "namespace" => return Err(self.error("the namespace axis is not supported")),
+                name => match Axis::find(name) {
+                    Some(axis) => axis,
+                    None => {
+                        self.index -= 2;
+                        return Err(self.error("unknown axis"));
+                    }
+                },
+            };
+        }
+
+        let test = if self.eat(&Token::Star) {
+            NodeTest::Element
+        } else {
+            let name = self.next_name()?;
+            if is_node_type(&name) && self.eat(&Token::LeftParen) {
+                let test = match name.as_str() {
+                    "node" => NodeTest::Node,
+                    "text" => NodeTest::Text,
+                    _ => {
+                        self.index -= 2;
+                        return Err(self.error("only node() and text() node types are supported"));
+                    }
+                };
+
+                self.expect(&Token::RightParen)?;
+                test
+            } else {
+                NodeTest::Name(name)
+            }
+        };
+
+        let predicates = self.parse_predicates()?;
+        path.steps.push(Step {
+            axis,
+            test,
+            predicates,
+        });
+        Ok(())
+    }
+
+    fn parse_attribute(&mut self, path: &mut Path) -> Result<(), ParseError> {
+        if self.peek() == Some(&Token::Star) {
+            return Err(self.error("attribute wildcards are not supported"));
+        }
+
+        path.attribute = Some(self.next_name()?);
+        if self.peek() == Some(&Token::LeftBracket) {
+            return Err(self.error("attributes cannot have predicates"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Path {
+    fn new(start: Start) -> Path {
+        Path {
+            start,
+            steps: Vec::new(),
+            attribute: None,
+        }
+    }
+}
+
+fn is_node_type(name: &str) -> bool {
+    ["node", "text", "comment", "processing-instruction"].contains(&name)
+}
+
+struct Context<'a, T: 'a> {
+    node: &'a Node<T>,
+    position: usize,
+    size: usize,
+}
+
+enum Value<T> {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Nodes(Vec<Node<T>>),
+    // The values of the selected attributes.
+    Strings(Vec<String>),
+}
+
+#[derive(Clone, Copy)]
+enum Atom<'a> {
+    Bool(bool),
+    Number(f64),
+    String(&'a str),
+}
+
+impl<T: XPathData> Value<T> {
+    fn boolean(&self) -> bool {
+        match *self {
+            Value::Bool(b) => b,
+            Value::Number(n) => n != 0.0 && !n.is_nan(),
+            Value::String(ref s) => !s.is_empty(),
+            Value::Nodes(ref nodes) => !nodes.is_empty(),
+            Value::Strings(ref strings) => !strings.is_empty(),
+        }
+    }
+
+    fn number(&self) -> f64 {
+        match *self {
+            Value::Bool(b) => Atom::Bool(b).number(),
+            Value::Number(n) => n,
+            _ => parse_number(&self.string()),
+        }
+    }
+
+    fn string(&self) -> String {
+        match *self {
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => number_to_string(n),
+            Value::String(ref s) => s.clone(),
+            Value::Nodes(ref nodes) => nodes.first().map(string_value).unwrap_or_default(),
+            Value::Strings(ref strings) => strings.first().cloned().unwrap_or_default(),
+        }
+    }
+
+    fn atom<'a>(&'a self) -> Option<Atom<'a>> {
+        match *self {
+            Value::Bool(b) => Some(Atom::Bool(b)),
+            Value::Number(n) => Some(Atom::Number(n)),
+            Value::String(ref s) => Some(Atom::String(s)),
+            Value::Nodes(_) | Value::Strings(_) => None,
+        }
+    }
+
+    fn strings(&self) -> Vec<String> {
+        match *self {
+            Value::Nodes(ref nodes) => nodes.iter().map(string_value).collect(),
+            Value::Strings(ref strings) => strings.clone(),
+            _ => vec![self.string()],
+        }
+    }
+}
+
+impl<'a> Atom<'a> {
+    fn boolean(self) -> bool {
+        match self {
+            Atom::Bool(b) => b,
+            Atom::Number(n) => n != 0.0 && !n.is_nan(),
+            Atom::String(s) => !s.is_empty(),
+        }
+    }
+
+    fn number(self) -> f64 {
+        match self {
+            Atom::Bool(true) => 1.0,
+            Atom::Bool(false) => 0.0,
+            Atom::Number(n) => n,
+            Atom::String(s) => parse_number(s),
+        }
+    }
+}
+
+// Returns the concatenated text of the node and its descendants.
+fn string_value<T: XPathData>(node: &Node<T>) -> String {
+    let mut value = String::new();
+    for node in node.descendants() {
+        if let Some(text) = node.borrow().text() {
+            value.push_str(text);
+        }
+    }
+
+    value
+}
+
+fn parse_number(s: &str) -> f64 {
+    let s = s.trim_matches(|c| c == ' ' || c == '\t' || c == '\r' || c == '\n');
+    // Unlike `f64::from_str`, only accept digits with an optional sign and decimal point.
+    let is_valid = s.chars().any(is_digit)
+        && s.chars()
+            .enumerate()
+            .all(|(i, c)| is_digit(c) || c == '.' || (c == '-' && i == 0))
+        && s.matches('.').count() <= 1;
+    if is_valid {
+        s.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == n.trunc() && n.abs() < 1e15 {
+        // Also turns a negative zero into `0`.
+        (n as i64).to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+fn evaluate<T: XPathData>(expr: &Expr, context: &Context<T>) -> Value<T> {
+    match *expr {
+        Expr::Literal(ref s) => Value::String(s.clone()),
+        Expr::Number(n) => Value::Number(n),
+        Expr::Negate(ref expr) => Value::Number(-evaluate(expr, context).number()),
+        Expr::Binary(op, ref left, ref right) => {
+            let left = evaluate(left, context);
+            match op {
+                Operator::Or => Value::Bool(left.boolean() || evaluate(right, context).boolean()),
+                Operator::And => Value::Bool(left.boolean() && evaluate(right, context).boolean()),
+                Operator::Equal
+                | Operator::NotEqual
+                | Operator::Less
+                | Operator::LessOrEqual
+                | Operator::Greater
+                | Operator::GreaterOrEqual => {
+                    Value::Bool(compare(op, &left, &evaluate(right, context)))
+                }
+                Operator::Plus => Value::Number(left.number() + evaluate(right, context).number()),
+                Operator::Minus => Value::Number(left.number() - evaluate(right, context).number()),
+                Operator::Multiply => {
+                    Value::Number(left.number() * evaluate(right, context).number())
+                }
+                Operator::Div => Value::Number(left.number() / evaluate(right, context).number()),
+                Operator::Mod => Value::Number(left.number() % evaluate(right, context).number()),
+            }
+        }
+        Expr::Function(function, ref args) => call(function, args, context),
+        Expr::Path(ref path) => {
+            let nodes = select_path(path, context);
+            match path.attribute {
+                Some(ref name) => Value::Strings(
+                    nodes
+                        .iter()
+                        .filter_map(|node| node.borrow().attribute(name).map(String::from))
+                        .collect(),
+                ),
+                None => Value::Nodes(nodes),
+            }
+        }
+    }
+}
+
+fn compare<T: XPathData>(op: Operator, left: &Value<T>, right: &Value<T>) -> bool {
+    match (left.atom(), right.atom()) {
+        (Some(left), Some(right)) => compare_atoms(op, left, right),
+        (Some(_), None) => compare(op.flipped(), right, left),
+        (None, right_atom) => {
+            // A set is compared by checking whether any of its values matches.
+            let left = left.strings();
+            match right_atom {
+                Some(Atom::Bool(b)) => {
+                    compare_atoms(op, Atom::Bool(!left.is_empty()), Atom::Bool(b))
+                }
+                Some(right) => left
+                    .iter()
+                    .any(|l| compare_atoms(op, Atom::String(l), right)),
+                None => {
+                    let right = right.strings();
+                    left.iter().any(|l| {
+                        right
+                            .iter()
+                            .any(|r| compare_atoms(op, Atom::String(l), Atom::String(r)))
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn compare_atoms(op: Operator, left: Atom, right: Atom) -> bool {
+    match op {
+        Operator::Equal | Operator::NotEqual => {
+            let equal = match (left, right) {
+                (Atom::Bool(_), _) | (_, Atom::Bool(_)) => left.boolean() == right.boolean(),
+                (Atom::Number(_), _) | (_, Atom::Number(_)) => left.number() == right.number(),
+                (Atom::String(left), Atom::String(right)) => left == right,
+            };
+            equal == (op == Operator::Equal)
+        }
+        Operator::Less => left.number() < right.number(),
+        Operator::LessOrEqual => left.number() <= right.number(),
+        Operator::Greater => left.number() > right.number(),
+        Operator::GreaterOrEqual => left.number() >= right.number(),
+        _ => unreachable!(),
+    }
+}
+
+fn call<T: XPathData>(function: Function, args: &[Expr], context: &Context<T>) -> Value<T> {
+    let arg = |index: usize| evaluate(&args[index], context);
+    // The optional argument of string functions defaults to the context node.
+    let string_arg = || match args.first() {
+        Some(arg) => evaluate(arg, context).string(),
+        None => string_value(context.node),
+    };
+
+    match function {
+        Function::Last => Value::Number(context.size as f64),
+        Function::Position => Value::Number(context.position as f64),
+        Function::Count => Value::Number(match arg(0) {
+            Value::Nodes(nodes) => nodes.len() as f64,
+            Value::Strings(strings) => strings.len() as f64,
+            _ => 0.0,
+        }),
+        Function::Name => {
+            let node = match args.first() {
+                Some(arg) => match evaluate(arg, context) {
+                    Value::Nodes(nodes) => nodes.into_iter().next(),
+                    _ => None,
+                },
+                None => Some(context.node.clone()),
+            };
+
+            let name = node.and_then(|node| node.borrow().name().map(String::from));
+            Value::String(name.unwrap_or_default())
+        }
+        Function::Not => Value::Bool(!arg(0).boolean()),
+        Function::True => Value::Bool(true),
+        Function::False => Value::Bool(false),
+        Function::Boolean => Value::Bool(arg(0).boolean()),
+        Function::Number => Value::Number(match args.first() {
+            Some(arg) => evaluate(arg, context).number(),
+            None => parse_number(&string_value(context.node)),
+        }),
+        Function::String => Value::String(string_arg()),
+        Function::Concat => Value::String(
+            args.iter()
+                .map(|arg| evaluate(arg, context).string())
+                .collect(),
+        ),
+        Function::Contains => Value::Bool(arg(0).string().contains(arg(1).string().as_str())),
+        Function::StartsWith => Value::Bool(arg(0).string().starts_with(arg(1).string().as_str())),
+        Function::StringLength => Value::Number(string_arg().chars().count() as f64),
+        Function::NormalizeSpace => {
+            let s = string_arg();
+            Value::String(s.split_whitespace().collect::<Vec<_>>().join(" "))
+        }
+    }
+}
+
+fn select_path<T: XPathData>(path: &Path, context: &Context<T>) -> Vec<Node<T>> {
+    let mut nodes = match path.start {
+        Start::Context => vec![context.node.clone()],
+        Start::Root => vec![context.node.root()],
+        Start::Filter(ref expr, ref predicates) => match evaluate(expr, context) {
+            Value::Nodes(nodes) => filter(nodes, predicates),
+            // Checked when parsing.
+            _ => Vec::new(),
+        },
+    };
+
+    for step in &path.steps {
+        nodes = select_step(step, &nodes);
+    }
+
+    nodes
+}
+
+fn select_step<T: XPathData>(step: &Step, context_nodes: &[Node<T>]) -> Vec<Node<T>> {
+    let mut nodes = Vec::new();
+    for node in context_nodes {
+        let selected = step.axis.select(node, &step.test);
+        nodes.extend(filter(selected, &step.predicates));
+    }
+
+    if context_nodes.len() > 1 {
+        Node::sort_in_document_order(&mut nodes);
+    } else if step.axis.is_reverse() {
+        nodes.reverse();
+    }
+
+    nodes
+}
+
+// Keeps the nodes for which all the predicates are true, in turn.
+fn filter<T: XPathData>(mut nodes: Vec<Node<T>>, predicates: &[Expr]) -> Vec<Node<T>> {
+    for predicate in predicates {
+        let size = nodes.len();
+        nodes = nodes
+            .into_iter()
+            .enumerate()
+            .filter(|&(index, ref node)| {
+                let context = Context {
+                    node,
+                    position: index + 1,
+                    size,
+                };
+
+                match evaluate(predicate, &context) {
+                    Value::Number(n) => n == context.position as f64,
+                    value => value.boolean(),
+                }
+            })
+            .map(|(_, node)| node)
+            .collect();
+    }
+
+    nodes
+}
@@ -1,8 +1,16 @@
 extern crate rctree;
 
-use rctree::{Node, NodeEdge};
+use rctree::diff::{self, Edit};
+use rctree::selectors::{self, Element, SelectorList};
+use rctree::sync::SyncNode;
+use rctree::xpath::{self, XPath, XPathData};
+use rctree::{
+    BorrowKind, BorrowedNode, DocumentPosition, MutationObserver, MutationRecord, Node, NodeEdge,
+    Transaction, TreeCursor, TreeError, TreePath, Visit, VisitResult, Visitor,
+};
 
 use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 
 #[test]
 fn it_works() {
@@ -141,6 +149,280 @@ fn insert_after_1() {
     node1.insert_after(node1_2);
 }
 
+#[test]
+fn append_2() {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    let node3 = Node::new(3);
+    node1.append(node2.clone());
+    node1.append(node3.clone());
+    node1.append(node2.clone());
+
+    assert_eq!(
+        format!("{:?}", TreePrinter(node1)),
+        "1
+    3
+    2
+"
+    );
+}
+
+// node1 -> node2 -> node3
+fn chain() -> (Node<i32>, Node<i32>, Node<i32>) {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    let node3 = Node::new(3);
+    node1.append(node2.clone());
+    node2.append(node3.clone());
+    (node1, node2, node3)
+}
+
+#[test]
+fn try_append_ancestor() {
+    let (node1, node2, node3) = chain();
+    assert_eq!(
+        node3.try_append(node1.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(
+        node3.try_append(node2.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(node2.parent(), Some(node1.clone()));
+    assert!(!node3.has_children());
+}
+
+#[test]
+fn try_prepend_ancestor() {
+    let (node1, node2, node3) = chain();
+    assert_eq!(
+        node3.try_prepend(node1.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(
+        node3.try_prepend(node2.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(node2.parent(), Some(node1.clone()));
+    assert!(!node3.has_children());
+}
+
+#[test]
+fn try_insert_after_ancestor() {
+    let (node1, node2, node3) = chain();
+    assert_eq!(
+        node3.try_insert_after(node1.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(
+        node3.try_insert_after(node2.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(node2.parent(), Some(node1.clone()));
+    assert_eq!(node3.next_sibling(), None);
+
+    // Siblings of ancestors are fine.
+    let node4 = Node::new(4);
+    node1.append(node4.clone());
+    assert_eq!(node3.try_insert_after(node4.clone()), Ok(()));
+    assert_eq!(node4.parent(), Some(node2));
+}
+
+#[test]
+fn try_insert_before_ancestor() {
+    let (node1, node2, node3) = chain();
+    assert_eq!(
+        node3.try_insert_before(node1.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(
+        node3.try_insert_before(node2.clone()),
+        Err(TreeError::InsertAncestor)
+    );
+    assert_eq!(node2.parent(), Some(node1.clone()));
+    assert_eq!(node3.previous_sibling(), None);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "a node cannot be inserted into its own subtree")]
+fn append_ancestor() {
+    let (node1, _, node3) = chain();
+    node3.append(node1);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "a node cannot be inserted into its own subtree")]
+fn prepend_ancestor() {
+    let (node1, _, node3) = chain();
+    node3.prepend(node1);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "a node cannot be inserted into its own subtree")]
+fn insert_after_ancestor() {
+    let (_, node2, node3) = chain();
+    node3.insert_after(node2);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "a node cannot be inserted into its own subtree")]
+fn insert_before_ancestor() {
+    let (_, node2, node3) = chain();
+    node3.insert_before(node2);
+}
+
+#[test]
+fn try_append_1() {
+    let node1 = Node::new(1);
+    let node1_2 = node1.clone();
+    assert_eq!(node1.try_append(node1_2), Err(TreeError::InsertSelf));
+}
+
+#[test]
+fn try_append_2() {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    node1.append(node2.clone());
+
+    let node3 = Node::new(3);
+    {
+        let _b = node2.borrow();
+        assert_eq!(
+            node1.try_append(node3.clone()),
+            Err(TreeError::AlreadyBorrowed {
+                node: BorrowedNode::Adjacent,
+                kind: BorrowKind::Shared,
+            })
+        );
+    }
+
+    assert_eq!(node3.parent(), None);
+    assert_eq!(node1.last_child(), Some(node2.clone()));
+    assert_eq!(node1.try_append(node3.clone()), Ok(()));
+    assert_eq!(node1.last_child(), Some(node3));
+}
+
+#[test]
+fn try_insert_before_1() {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    node1.append(node2.clone());
+
+    let node3 = Node::new(3);
+    {
+        let _b = node3.borrow_mut();
+        assert_eq!(
+            node2.try_insert_before(node3.clone()),
+            Err(TreeError::AlreadyBorrowed {
+                node: BorrowedNode::Other,
+                kind: BorrowKind::Mutable,
+            })
+        );
+    }
+
+    assert_eq!(node1.first_child(), Some(node2.clone()));
+    assert_eq!(node2.try_insert_before(node3.clone()), Ok(()));
+    assert_eq!(node1.first_child(), Some(node3));
+}
+
+#[test]
+fn try_detach_1() {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    node1.append(node2.clone());
+
+    {
+        let _b = node1.borrow_mut();
+        assert!(node2.try_detach().is_err());
+    }
+
+    assert_eq!(node2.parent(), Some(node1.clone()));
+    assert_eq!(node2.try_detach(), Ok(()));
+    assert_eq!(node2.parent(), None);
+    assert!(!node1.has_children());
+}
+
+#[test]
+fn map_1() {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    let node3 = Node::new(3);
+    node1.append(node2.clone());
+    node2.append(node3.clone());
+    node1.append(Node::new(4));
+
+    let mapped = node1.map(|data| format!("n{}", data));
+    let data: Vec<String> = mapped
+        .descendants()
+        .map(|node| node.borrow().clone())
+        .collect();
+    assert_eq!(data, ["n1", "n2", "n3", "n4"]);
+    assert_eq!(mapped.parent(), None);
+    assert_eq!(node2.map(|data| data * 10).descendants().count(), 2);
+}
+
+#[test]
+fn try_map_1() {
+    let node1 = Node::new(1);
+    node1.append(Node::new(2));
+    node1.append(Node::new(-3));
+    node1.append(Node::new(4));
+
+    let mut seen = Vec::new();
+    let result = node1.try_map(|&data| {
+        seen.push(data);
+        if data < 0 {
+            Err(data)
+        } else {
+            Ok(data as u32)
+        }
+    });
+    assert_eq!(result.err(), Some(-3));
+    assert_eq!(seen, [1, 2, -3]);
+
+    let mapped = node1.try_map(|&data| Ok::<i32, ()>(data * 2)).unwrap();
+    assert_eq!(to_data(mapped.descendants()), &[2, 4, -6, 8]);
+}
+
+#[test]
+fn try_borrow_1() {
+    let node1 = Node::new(1);
+    let _b = node1.borrow_mut();
+    assert!(node1.try_borrow().is_err());
+    assert!(node1.try_borrow_mut().is_err());
+    assert!(node1.try_parent().is_err());
+    assert!(node1.try_make_deep_copy().is_err());
+}
+
+#[test]
+fn try_iter_1() {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    let node3 = Node::new(3);
+    node1.append(node2.clone());
+    node1.append(node3.clone());
+
+    let _b = node2.borrow_mut();
+
+    let children: Vec<_> = node1.try_children().collect();
+    assert_eq!(children.len(), 1);
+    assert!(children[0].is_err());
+
+    let descendants: Vec<_> = node1.try_descendants().collect();
+    assert_eq!(descendants.len(), 2);
+    assert_eq!(descendants[0], Ok(node1.clone()));
+    assert!(descendants[1].is_err());
+
+    let siblings: Vec<_> = node3.try_preceding_siblings().collect();
+    assert_eq!(siblings.len(), 2);
+    assert_eq!(siblings[0], Ok(node3.clone()));
+    assert!(siblings[1].is_err());
+}
+
 #[test]
 #[should_panic]
 fn iter_1() {
@@ -163,6 +445,105 @@ fn stack_overflow() {
     }
 }
 
+#[test]
+fn make_deep_copy_stack_overflow() {
+    let root = deep_chain(200_000);
+    let copy = root.make_deep_copy();
+    assert_eq!(copy.descendants().count(), 200_000);
+    assert_eq!(
+        copy.descendants().last().map(|node| *node.borrow()),
+        Some(199_999)
+    );
+    assert!(root.try_make_deep_copy().is_ok());
+}
+
+#[test]
+fn map_stack_overflow() {
+    let root = deep_chain(200_000);
+    let mapped = root.map(|&data| i64::from(data) * 2);
+    assert_eq!(
+        mapped.descendants().last().map(|node| *node.borrow()),
+        Some(399_998)
+    );
+
+    let result = root.try_map(|&data| if data == 199_999 { Err(data) } else { Ok(data) });
+    assert_eq!(result.err(), Some(199_999));
+}
+
+#[test]
+fn deep_copy_with_map_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (2, 3), (1, 4)]);
+    let (copy, map) = root.deep_copy_with_map();
+    assert_eq!(tree_to_string(&copy), tree_to_string(&root));
+    assert_eq!(map.len(), 5);
+    assert_eq!(map.get(&root), Some(&copy));
+    assert_eq!(map.get(&copy), None);
+
+    for (node, node_copy) in root.descendants().zip(copy.descendants()) {
+        assert_eq!(map.get(&node), Some(&node_copy));
+        assert!(node != node_copy);
+    }
+
+    let (_, map) = deep_chain(200_000).deep_copy_with_map();
+    assert_eq!(map.len(), 200_000);
+}
+
+#[test]
+fn deep_copy_filtered_1() {
+    let (root, _) = sample_tree();
+
+    let copy = root
+        .deep_copy_filtered(|&data| data != 3 && data != 5)
+        .unwrap();
+    assert_eq!(
+        tree_to_string(&copy),
+        "0
+    1
+        2
+"
+    );
+    assert!(root.deep_copy_filtered(|&data| data != 0).is_none());
+
+    let mut seen = Vec::new();
+    root.deep_copy_filtered(|&data| {
+        seen.push(data);
+        data != 3
+    });
+    assert_eq!(seen, [0, 1, 2, 3, 5, 6]);
+}
+
+#[test]
+fn deep_copy_to_depth_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (3, 3), (1, 4)]);
+
+    assert_eq!(tree_to_string(&root.deep_copy_to_depth(0)), "0\n");
+    assert_eq!(
+        tree_to_string(&root.deep_copy_to_depth(1)),
+        "0
+    1
+    4
+"
+    );
+    assert_eq!(root.deep_copy_to_depth(2).descendants().count(), 4);
+    assert_eq!(
+        tree_to_string(&root.deep_copy_to_depth(10)),
+        tree_to_string(&root)
+    );
+}
+
+// A chain of nodes with data from 0 at the root to `depth - 1`.
+fn deep_chain(depth: i32) -> Node<i32> {
+    let root = Node::new(0);
+    let mut parent = root.clone();
+    for i in 1..depth {
+        let node = Node::new(i);
+        parent.append(node.clone());
+        parent = node;
+    }
+
+    root
+}
+
 fn fan_tree(depth: i32, width: usize) -> Node<i32> {
     let node = Node::new(depth);
     if depth > 0 {
@@ -350,3 +731,1937 @@ fn traverse_2() {
     assert!(traverse.next().is_none());
     assert!(traverse.next_back().is_none());
 }
+
+#[test]
+fn sync_1() {
+    let a = SyncNode::new(1);
+    a.append(SyncNode::new(2));
+    a.append(SyncNode::new(3));
+    a.prepend(SyncNode::new(4));
+    let b = SyncNode::new(5);
+    b.append(a.clone());
+    a.insert_before(SyncNode::new(6));
+    a.insert_before(SyncNode::new(7));
+    a.insert_after(SyncNode::new(8));
+    a.insert_after(SyncNode::new(9));
+    let c = SyncNode::new(10);
+    b.append(c.clone());
+    c.previous_sibling().unwrap().detach();
+
+    assert_eq!(
+        b.descendants()
+            .map(|node| *node.borrow())
+            .collect::<Vec<_>>(),
+        [5, 6, 7, 1, 4, 2, 3, 9, 10]
+    );
+    assert_eq!(
+        b.traverse()
+            .rev()
+            .filter_map(|edge| match edge {
+                rctree::sync::NodeEdge::Start(node) => Some(*node.borrow()),
+                rctree::sync::NodeEdge::End(_) => None,
+            })
+            .collect::<Vec<_>>(),
+        [10, 9, 3, 2, 4, 1, 7, 6, 5]
+    );
+    assert_eq!(b.children().rev().count(), 5);
+    assert_eq!(b.make_deep_copy().descendants().count(), 9);
+
+    // Re-appending an existing child moves it to the end.
+    b.append(b.first_child().unwrap());
+    let last = b.last_child().unwrap();
+    assert_eq!(*last.borrow(), 6);
+}
+
+#[test]
+fn sync_threads() {
+    use std::thread;
+
+    let root = SyncNode::new(0);
+    for i in 1..10 {
+        let child = SyncNode::new(i);
+        child.append(SyncNode::new(i * 10));
+        root.append(child);
+    }
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let root = root.clone();
+            thread::spawn(move || root.descendants().map(|n| *n.borrow()).sum::<i32>())
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 495);
+    }
+
+    let child = root.first_child().unwrap();
+    thread::spawn(move || child.detach()).join().unwrap();
+    assert_eq!(root.children().count(), 8);
+}
+
+#[test]
+fn sync_stack_overflow() {
+    let mut parent = SyncNode::new(1);
+    for _ in 0..200_000 {
+        let node = SyncNode::new(1);
+        node.append(parent.clone());
+        parent = node;
+    }
+}
+
+#[test]
+fn sync_make_deep_copy_stack_overflow() {
+    let mut root = SyncNode::new(0);
+    for i in 1..200_000 {
+        let node = SyncNode::new(i);
+        node.append(root.clone());
+        root = node;
+    }
+
+    let copy = root.make_deep_copy();
+    assert_eq!(copy.descendants().count(), 200_000);
+    assert_eq!(
+        copy.descendants().last().map(|node| *node.borrow()),
+        Some(0)
+    );
+}
+
+#[test]
+fn sync_weak_1() {
+    let weak;
+
+    {
+        let node1 = SyncNode::new("node1");
+        weak = node1.downgrade();
+        assert_eq!(weak.upgrade(), Some(node1.clone()));
+    }
+
+    assert_eq!(None, weak.upgrade());
+}
+
+#[test]
+fn arena_1() {
+    use rctree::arena::Tree;
+
+    let mut tree = Tree::new();
+    let a = tree.new_node(1);
+    let n = tree.new_node(2);
+    tree.append(a, n);
+    let n = tree.new_node(3);
+    tree.append(a, n);
+    let n = tree.new_node(4);
+    tree.prepend(a, n);
+    let b = tree.new_node(5);
+    tree.append(b, a);
+    let n = tree.new_node(6);
+    tree.insert_before(a, n);
+    let n = tree.new_node(7);
+    tree.insert_before(a, n);
+    let n = tree.new_node(8);
+    tree.insert_after(a, n);
+    let n = tree.new_node(9);
+    tree.insert_after(a, n);
+    let c = tree.new_node(10);
+    tree.append(b, c);
+    let removed = tree.previous_sibling(c).unwrap();
+    assert_eq!(tree.remove(removed), 8);
+    assert!(!tree.contains(removed));
+    assert_eq!(tree.len(), 9);
+
+    assert_eq!(
+        tree.descendants(b).map(|id| tree[id]).collect::<Vec<_>>(),
+        [5, 6, 7, 1, 4, 2, 3, 9, 10]
+    );
+    assert_eq!(
+        tree.children(b)
+            .rev()
+            .map(|id| tree[id])
+            .collect::<Vec<_>>(),
+        [10, 9, 1, 7, 6]
+    );
+    assert_eq!(tree.ancestors(c).collect::<Vec<_>>(), [c, b]);
+
+    // A reused slot does not revive the old id.
+    let reused = tree.new_node(11);
+    assert_eq!(tree.get(removed), None);
+    assert_eq!(tree.get(reused), Some(&11));
+}
+
+#[test]
+fn arena_traverse_1() {
+    use rctree::arena::{NodeEdge, Tree};
+
+    let mut tree = Tree::new();
+    let node1 = tree.new_node("node1");
+    let node2 = tree.new_node("node2");
+    let node3 = tree.new_node("node3");
+    tree.append(node1, node2);
+    tree.append(node1, node3);
+
+    assert_eq!(
+        tree.traverse(node1).collect::<Vec<_>>(),
+        [
+            NodeEdge::Start(node1),
+            NodeEdge::Start(node2),
+            NodeEdge::End(node2),
+            NodeEdge::Start(node3),
+            NodeEdge::End(node3),
+            NodeEdge::End(node1),
+        ]
+    );
+    assert_eq!(
+        tree.traverse(node1).rev().collect::<Vec<_>>(),
+        [
+            NodeEdge::End(node1),
+            NodeEdge::End(node3),
+            NodeEdge::Start(node3),
+            NodeEdge::End(node2),
+            NodeEdge::Start(node2),
+            NodeEdge::Start(node1),
+        ]
+    );
+}
+
+#[test]
+fn arena_node_conversion() {
+    use rctree::arena::Tree;
+
+    let root = fan_tree(3, 3);
+    let (tree, id) = Tree::from_node(&root);
+    assert_eq!(tree.len(), 40);
+    assert_eq!(
+        tree.descendants(id).map(|id| tree[id]).collect::<Vec<_>>(),
+        root.descendants().map(|n| *n.borrow()).collect::<Vec<_>>()
+    );
+
+    let copy = tree.to_node(id);
+    assert_eq!(
+        format!("{:?}", TreePrinter(copy)),
+        format!("{:?}", TreePrinter(root.clone()))
+    );
+
+    let moved = tree.into_node(id);
+    assert_eq!(
+        format!("{:?}", TreePrinter(moved)),
+        format!("{:?}", TreePrinter(root))
+    );
+}
+
+#[test]
+fn arena_deep_copy() {
+    use rctree::arena::Tree;
+
+    let mut tree = Tree::new();
+    let node1 = tree.new_node(1);
+    let node2 = tree.new_node(2);
+    tree.append(node1, node2);
+    let copy = tree.make_deep_copy(node1);
+    tree.append(node2, copy);
+
+    assert_eq!(
+        tree.descendants(node1)
+            .map(|id| tree[id])
+            .collect::<Vec<_>>(),
+        [1, 2, 1, 2]
+    );
+}
+
+#[test]
+fn position_1() {
+    let root = fan_tree(3, 3);
+    let node = root.nth_child(2).unwrap().nth_child(1).unwrap();
+    assert_eq!(node.index(), 1);
+    assert_eq!(node.depth(), 2);
+    assert_eq!(node.child_count(), 3);
+    assert_eq!(root.index(), 0);
+    assert_eq!(root.depth(), 0);
+    assert_eq!(root.nth_child(3), None);
+}
+
+#[test]
+fn tree_path_1() {
+    let root = fan_tree(3, 3);
+    let node = root
+        .nth_child(0)
+        .unwrap()
+        .nth_child(2)
+        .unwrap()
+        .nth_child(1)
+        .unwrap();
+
+    let path = node.path();
+    assert_eq!(path.indices(), &[0, 2, 1]);
+    assert_eq!(path.to_string(), "/0/2/1");
+    assert_eq!("/0/2/1".parse::<TreePath>(), Ok(path.clone()));
+    assert_eq!(root.resolve(&path), Some(node));
+    assert_eq!(root.resolve(&TreePath::from(vec![0, 3])), None);
+
+    assert_eq!(root.path(), TreePath::new());
+    assert_eq!(TreePath::new().to_string(), "/");
+    assert_eq!("/".parse::<TreePath>(), Ok(TreePath::new()));
+    assert!("".parse::<TreePath>().is_err());
+    assert!("0/1".parse::<TreePath>().is_err());
+    assert!("/0//1".parse::<TreePath>().is_err());
+    assert!("/0/+1".parse::<TreePath>().is_err());
+    assert!("/0/1/".parse::<TreePath>().is_err());
+}
+
+fn edit_to_string(edit: &Edit<i32>) -> String {
+    match *edit {
+        Edit::Update { ref path, data } => format!("update {} {}", path, data),
+        Edit::Insert {
+            ref parent,
+            index,
+            ref node,
+        } => format!("insert {}:{} {}", parent, index, node.borrow()),
+        Edit::Delete { ref path } => format!("delete {}", path),
+        Edit::Move {
+            ref from,
+            ref parent,
+            index,
+        } => format!("move {} {}:{}", from, parent, index),
+    }
+}
+
+// Builds a tree from `(depth, data)` pairs in tree order.
+fn tree_from(nodes: &[(usize, i32)]) -> Node<i32> {
+    let root = Node::new(nodes[0].1);
+    let mut stack = vec![root.clone()];
+    for &(depth, data) in &nodes[1..] {
+        stack.truncate(depth);
+        let node = Node::new(data);
+        stack.last().unwrap().append(node.clone());
+        stack.push(node);
+    }
+
+    root
+}
+
+// A tree which data is the index of the node in tree order,
+// along with all its nodes in that order:
+//
+// 0
+// ├── 1
+// │   ├── 2
+// │   └── 3
+// │       └── 4
+// └── 5
+//     └── 6
+fn sample_tree() -> (Node<i32>, Vec<Node<i32>>) {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (2, 3), (3, 4), (1, 5), (2, 6)]);
+    let nodes = root.descendants().collect();
+    (root, nodes)
+}
+
+fn check_diff(old: &Node<i32>, new: &Node<i32>) -> Vec<String> {
+    let edits = diff::diff(old, new);
+    let tree = old.make_deep_copy();
+    diff::apply(&tree, &edits).unwrap();
+    assert_eq!(
+        format!("{:?}", TreePrinter(tree)),
+        format!("{:?}", TreePrinter(new.clone()))
+    );
+
+    edits.iter().map(edit_to_string).collect()
+}
+
+#[test]
+fn diff_1() {
+    let old = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3), (1, 4), (1, 7)]);
+    let new = tree_from(&[(0, 0), (1, 3), (1, 1), (2, 2), (2, 5), (1, 6)]);
+
+    assert_eq!(
+        check_diff(&old, &new),
+        &["delete /3", "move /1 /:0", "insert /1:1 5", "update /2 6"]
+    );
+    assert!(check_diff(&old, &old).is_empty());
+}
+
+#[test]
+fn diff_reorder() {
+    let old = tree_from(&[(0, 0), (1, 1), (1, 2), (2, 5), (1, 3), (1, 4)]);
+    let new = tree_from(&[(0, 0), (1, 4), (1, 3), (1, 2), (2, 5), (1, 1)]);
+    assert_eq!(
+        check_diff(&old, &new),
+        &["move /1 /:0", "move /2 /:0", "move /3 /:0"]
+    );
+
+    let new = tree_from(&[(0, 0), (1, 2), (2, 5), (1, 3), (1, 4), (1, 1)]);
+    assert_eq!(check_diff(&old, &new), &["move /0 /:3"]);
+}
+
+#[test]
+fn diff_random() {
+    // A simple deterministic generator.
+    let mut seed = 1u32;
+    let mut next = move |max: u32| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) % max
+    };
+
+    for _ in 0..200 {
+        let mut trees = Vec::new();
+        for _ in 0..2 {
+            let mut nodes = vec![(0, next(3) as i32)];
+            for _ in 0..next(20) {
+                let depth = nodes.last().unwrap().0 + 1;
+                nodes.push((1 + next(depth as u32) as usize, next(5) as i32));
+            }
+            trees.push(tree_from(&nodes));
+        }
+
+        check_diff(&trees[0], &trees[1]);
+        check_diff(&trees[1], &trees[0]);
+    }
+}
+
+#[test]
+fn diff_apply_error() {
+    let root = tree_from(&[(0, 0), (1, 1)]);
+    let edits = vec![
+        Edit::Update {
+            path: TreePath::from(vec![0]),
+            data: 2,
+        },
+        Edit::Move {
+            from: TreePath::from(vec![0]),
+            parent: TreePath::new(),
+            index: 2,
+        },
+    ];
+
+    assert_eq!(
+        diff::apply(&root, &edits),
+        Err(diff::ApplyError { edit: 1 })
+    );
+    let first = root.first_child().unwrap();
+    assert_eq!(*first.borrow(), 2);
+    assert_eq!(root.child_count(), 1);
+}
+
+#[test]
+fn diff_stack_overflow() {
+    let old = Node::new(0);
+    let mut prev = old.clone();
+    for i in 1..10000 {
+        let node = Node::new(i);
+        prev.append(node.clone());
+        prev = node;
+    }
+
+    let new = old.make_deep_copy();
+    let new_last = new.descendants().last().unwrap();
+    *new_last.borrow_mut() = -1;
+
+    let edits = diff::diff(&old, &new);
+    assert_eq!(edits.len(), 1);
+    diff::apply(&old, &edits).unwrap();
+    let old_last = old.descendants().last().unwrap();
+    assert_eq!(*old_last.borrow(), -1);
+}
+
+fn record_to_string(record: &MutationRecord<i32>) -> String {
+    let to_string = |nodes: &[Node<i32>]| {
+        nodes
+            .iter()
+            .map(|node| node.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let sibling = |node: &Option<Node<i32>>| match *node {
+        Some(ref node) => node.to_string(),
+        None => "-".to_string(),
+    };
+
+    match *record {
+        MutationRecord::ChildListChanged {
+            ref parent,
+            ref added,
+            ref removed,
+            ref previous_sibling,
+            ref next_sibling,
+        } => format!(
+            "{}: +[{}] -[{}] {} {}",
+            parent,
+            to_string(added),
+            to_string(removed),
+            sibling(previous_sibling),
+            sibling(next_sibling)
+        ),
+        MutationRecord::DataChanged { ref node } => format!("{}: data", node),
+    }
+}
+
+fn take_records(observer: &MutationObserver<i32>) -> Vec<String> {
+    observer
+        .take_records()
+        .iter()
+        .map(record_to_string)
+        .collect()
+}
+
+#[test]
+fn observer_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3)]);
+    let child = root.first_child().unwrap();
+
+    let observer = MutationObserver::new();
+    observer.observe(&root, false);
+
+    root.append(Node::new(4));
+    root.prepend(Node::new(5));
+    child.insert_after(Node::new(6));
+    child.insert_before(Node::new(7));
+    child.append(Node::new(8));
+    child.detach();
+    child.detach();
+    assert_eq!(
+        take_records(&observer),
+        &[
+            "0: +[4] -[] 3 -",
+            "0: +[5] -[] - 1",
+            "0: +[6] -[] 1 3",
+            "0: +[7] -[] 5 1",
+            "0: +[] -[1] 7 6",
+        ]
+    );
+
+    // Moving a node is a removal followed by an addition.
+    let last = root.last_child().unwrap();
+    root.prepend(last);
+    assert_eq!(
+        take_records(&observer),
+        &["0: +[] -[4] 3 -", "0: +[4] -[] - 5"]
+    );
+
+    observer.disconnect();
+    root.append(Node::new(9));
+    assert!(observer.take_records().is_empty());
+}
+
+#[test]
+fn observer_subtree() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3)]);
+    let child = root.first_child().unwrap();
+    let grandchild = child.first_child().unwrap();
+
+    let observer = MutationObserver::new();
+    observer.observe(&root, true);
+    observer.observe(&child, true);
+    let child_observer = MutationObserver::new();
+    child_observer.observe(&child, false);
+
+    grandchild.append(Node::new(4));
+    child.append(Node::new(5));
+
+    // Each change is reported to an observer once.
+    assert_eq!(
+        take_records(&observer),
+        &["2: +[4] -[] - -", "1: +[5] -[] 2 -"]
+    );
+    assert_eq!(take_records(&child_observer), &["1: +[5] -[] 2 -"]);
+
+    *grandchild.borrow_mut() = 6;
+    grandchild.notify_data_changed();
+    assert_eq!(take_records(&observer), &["6: data"]);
+    assert!(child_observer.take_records().is_empty());
+
+    // Registrations are removed along with the observer.
+    drop(observer);
+    root.append(Node::new(7));
+    assert!(child_observer.take_records().is_empty());
+}
+
+fn tree_to_string(root: &Node<i32>) -> String {
+    format!("{:?}", TreePrinter(root.clone()))
+}
+
+#[test]
+fn transaction_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (2, 3), (1, 4)]);
+    let initial = tree_to_string(&root);
+    let child = root.first_child().unwrap();
+    let last = root.last_child().unwrap();
+
+    let mut transaction = Transaction::new();
+    transaction.append(&root, Node::new(5));
+    transaction.prepend(&last, child.first_child().unwrap());
+    transaction.insert_before(&child, child.last_child().unwrap());
+    transaction.insert_after(&root.first_child().unwrap(), last.clone());
+    transaction.detach(&child);
+    transaction.replace_data(&root, 6);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[(0, 6), (1, 3), (1, 4), (2, 2), (1, 5)]))
+    );
+
+    transaction.rollback();
+    assert_eq!(tree_to_string(&root), initial);
+
+    let mut transaction = Transaction::new();
+    transaction.detach(&last);
+    transaction.commit();
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[(0, 0), (1, 1), (2, 2), (2, 3)]))
+    );
+}
+
+#[test]
+fn transaction_savepoints() {
+    let root = tree_from(&[(0, 0), (1, 1), (1, 2)]);
+    let initial = tree_to_string(&root);
+
+    {
+        let mut transaction = Transaction::new();
+        transaction.append(&root, Node::new(3));
+        let savepoint_1 = transaction.savepoint();
+        let after_append = tree_to_string(&root);
+
+        transaction.replace_data(&root.first_child().unwrap(), 4);
+        let savepoint_2 = transaction.savepoint();
+        transaction.detach(&root.first_child().unwrap());
+        transaction.rollback_to(savepoint_2);
+        assert_eq!(
+            tree_to_string(&root),
+            tree_to_string(&tree_from(&[(0, 0), (1, 4), (1, 2), (1, 3)]))
+        );
+
+        transaction.rollback_to(savepoint_1);
+        assert_eq!(tree_to_string(&root), after_append);
+
+        // Rolling back to an outer savepoint discards the inner ones.
+        transaction.rollback_to(savepoint_2);
+        assert_eq!(tree_to_string(&root), after_append);
+
+        // Dropping a transaction rolls it back.
+        transaction.prepend(&root, root.last_child().unwrap());
+    }
+
+    assert_eq!(tree_to_string(&root), initial);
+}
+
+#[test]
+fn transaction_roots() {
+    // A detached node is restored next to its former siblings even without a parent.
+    let first = Node::new(1);
+    let second = Node::new(2);
+    first.insert_after(second.clone());
+
+    let mut transaction = Transaction::new();
+    transaction.detach(&first);
+    assert_eq!(second.previous_sibling(), None);
+    transaction.rollback();
+    assert_eq!(second.previous_sibling(), Some(first));
+}
+
+#[test]
+#[should_panic(expected = "unwinding")]
+fn transaction_panic() {
+    let root = Node::new(0);
+    // Declared before the transaction, so it is still borrowed when the transaction is dropped.
+    let _data;
+    let mut transaction = Transaction::new();
+    transaction.append(&root, Node::new(1));
+    _data = root.borrow_mut();
+    panic!("unwinding");
+}
+
+#[test]
+fn cursor_1() {
+    let mut cursor = TreeCursor::new(tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3)]));
+    assert!(!cursor.to_parent());
+    assert!(!cursor.to_next_sibling());
+
+    // The cursor is the only reference to the tree.
+    assert!(cursor.to_last_child());
+    assert_eq!(*cursor.node().borrow(), 3);
+    assert!(cursor.to_previous_sibling());
+    assert!(!cursor.to_previous_sibling());
+    assert!(cursor.to_first_child());
+    assert_eq!(*cursor.node().borrow(), 2);
+    assert!(!cursor.to_first_child());
+    assert!(cursor.to_parent());
+    assert_eq!(*cursor.node().borrow(), 1);
+    assert!(cursor.to_next_sibling());
+    assert!(cursor.to_root());
+    assert_eq!(*cursor.node().borrow(), 0);
+    assert!(!cursor.to_root());
+}
+
+#[test]
+fn cursor_edit() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3)]);
+    let mut cursor = TreeCursor::new(root.clone());
+    cursor.to_first_child();
+
+    cursor.insert_before(Node::new(4));
+    cursor.insert_after(Node::new(5));
+    assert_eq!(*cursor.node().borrow(), 1);
+
+    let replaced = cursor.replace(Node::new(6));
+    assert_eq!(*replaced.borrow(), 1);
+    assert_eq!(replaced.parent(), None);
+    assert_eq!(*cursor.node().borrow(), 6);
+
+    assert_eq!(cursor.remove().map(|node| *node.borrow()), Some(6));
+    assert_eq!(*cursor.node().borrow(), 5);
+    cursor.to_next_sibling();
+    assert_eq!(cursor.remove().map(|node| *node.borrow()), Some(3));
+    assert_eq!(*cursor.node().borrow(), 0);
+    assert_eq!(cursor.remove(), None);
+
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[(0, 0), (1, 4), (1, 5)]))
+    );
+}
+
+// Checks that the weak `parent`, `last_child` and `previous_sibling` links
+// agree with the strong ones.
+fn assert_links<T>(root: &Node<T>) {
+    for node in root.descendants() {
+        let children: Vec<_> = node.children().collect();
+        let mut reversed: Vec<_> = node.children().rev().collect();
+        reversed.reverse();
+        assert!(children == reversed);
+        assert!(children
+            .iter()
+            .all(|child| child.parent() == Some(node.clone())));
+    }
+}
+
+#[test]
+fn replace_with_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3), (1, 4)]);
+    let node = root.nth_child(1).unwrap();
+    node.replace_with(Node::new(5));
+    root.first_child()
+        .unwrap()
+        .replace_with(root.last_child().unwrap());
+    assert_eq!(node.parent(), None);
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[(0, 0), (1, 4), (1, 5)]))
+    );
+}
+
+#[test]
+#[should_panic]
+fn replace_with_2() {
+    let node1 = Node::new(1);
+    let node1_2 = node1.clone();
+    node1.replace_with(node1_2);
+}
+
+#[test]
+fn swap_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (2, 3), (1, 4), (1, 5)]);
+    let node1 = root.first_child().unwrap();
+    let node2 = node1.first_child().unwrap();
+    let node3 = node2.next_sibling().unwrap();
+    let node4 = node1.next_sibling().unwrap();
+    let node5 = root.last_child().unwrap();
+
+    // Adjacent siblings, in both orders.
+    node2.swap(&node3);
+    node2.swap(&node3);
+    node4.swap(&node1);
+    // Different parents.
+    node2.swap(&node5);
+    // Siblings at the ends.
+    node4.swap(&node2);
+    node4.swap(&node4);
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[
+            (0, 0),
+            (1, 2),
+            (1, 1),
+            (2, 5),
+            (2, 3),
+            (1, 4)
+        ]))
+    );
+}
+
+#[test]
+#[should_panic(expected = "a node cannot be swapped with its ancestor")]
+fn swap_2() {
+    let (node1, _, node3) = chain();
+    node3.swap(&node1);
+}
+
+#[test]
+#[should_panic(expected = "a node cannot be swapped with its ancestor")]
+fn swap_3() {
+    let (node1, node2, _) = chain();
+    node1.swap(&node2);
+}
+
+#[test]
+fn wrap_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3)]);
+    let node1 = root.first_child().unwrap();
+    node1.wrap(Node::new(4));
+    root.last_child().unwrap().wrap(Node::new(5));
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[
+            (0, 0),
+            (1, 4),
+            (2, 1),
+            (3, 2),
+            (1, 5),
+            (2, 3)
+        ]))
+    );
+
+    node1.unwrap();
+    root.first_child().unwrap().unwrap();
+    assert_eq!(node1.parent(), None);
+    assert_eq!(node1.first_child(), None);
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[(0, 0), (1, 2), (1, 5), (2, 3)]))
+    );
+}
+
+#[test]
+#[should_panic]
+fn wrap_2() {
+    let node1 = Node::new(1);
+    let node1_2 = node1.clone();
+    node1.wrap(node1_2);
+}
+
+#[test]
+fn unwrap_1() {
+    let node1 = Node::new(1);
+    let node2 = Node::new(2);
+    let node3 = Node::new(3);
+    node1.append(node2.clone());
+    node1.append(node3.clone());
+    node1.unwrap();
+
+    assert_eq!(node1.first_child(), None);
+    assert_eq!(node2.parent(), None);
+    assert_eq!(node2.next_sibling(), None);
+    assert_eq!(node3.parent(), None);
+    assert_eq!(node3.previous_sibling(), None);
+}
+
+#[test]
+fn append_all_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (1, 2), (1, 3)]);
+    let other = tree_from(&[(0, 4), (1, 5)]);
+    let children = root.take_children();
+    assert_eq!(root.first_child(), None);
+    assert_eq!(root.last_child(), None);
+    assert!(children.iter().all(|child| child.parent().is_none()));
+
+    let first = children[0].clone();
+    other.append_all(children);
+    // Duplicates end up at their last position, like with `append`.
+    other.append_all(vec![first.clone(), other.first_child().unwrap(), first]);
+    assert_links(&other);
+    assert_eq!(
+        tree_to_string(&other),
+        tree_to_string(&tree_from(&[(0, 4), (1, 2), (1, 3), (1, 5), (1, 1)]))
+    );
+}
+
+#[test]
+fn append_all_2() {
+    // A failed call leaves the tree unchanged.
+    let root = tree_from(&[(0, 0), (1, 1), (1, 2), (1, 3)]);
+    let other = tree_from(&[(0, 4), (1, 5)]);
+    let initial = tree_to_string(&root);
+    let first = root.first_child().unwrap();
+    let last = root.last_child().unwrap();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        other.append_all(vec![first.clone(), other.clone()]);
+    }));
+    assert!(result.is_err());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _data = last.borrow_mut();
+        other.append_all(vec![first.clone(), last.clone()]);
+    }));
+    assert!(result.is_err());
+
+    assert_links(&root);
+    assert_eq!(tree_to_string(&root), initial);
+    assert_eq!(other.children().count(), 1);
+}
+
+#[test]
+fn split_children_at_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (1, 2), (2, 3), (1, 4)]);
+    let other = tree_from(&[(0, 5), (1, 6)]);
+    root.split_children_at(1, &other);
+    root.split_children_at(1, &other);
+    assert_links(&root);
+    assert_links(&other);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[(0, 0), (1, 1)]))
+    );
+    assert_eq!(
+        tree_to_string(&other),
+        tree_to_string(&tree_from(&[(0, 5), (1, 6), (1, 2), (2, 3), (1, 4)]))
+    );
+
+    other.split_children_at(0, &root);
+    assert_eq!(other.first_child(), None);
+    assert_eq!(root.child_count(), 4);
+}
+
+#[test]
+#[should_panic(expected = "index 2 is out of bounds")]
+fn split_children_at_2() {
+    let root = tree_from(&[(0, 0), (1, 1)]);
+    root.split_children_at(2, &Node::new(2));
+}
+
+#[test]
+fn split_children_at_3() {
+    let root = tree_from(&[(0, 0), (1, 1), (1, 2), (2, 3)]);
+    let expected = tree_to_string(&root);
+    let observer = MutationObserver::new();
+    observer.observe(&root, true);
+
+    for new_parent in root.descendants().skip(2) {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            root.split_children_at(1, &new_parent);
+        }));
+        assert!(result.is_err());
+    }
+    assert_links(&root);
+    assert_eq!(tree_to_string(&root), expected);
+    assert!(observer.take_records().is_empty());
+
+    // A child before the split point can be the new parent.
+    let first = root.first_child().unwrap();
+    root.split_children_at(1, &first);
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[(0, 0), (1, 1), (2, 2), (3, 3)]))
+    );
+}
+
+#[test]
+fn sort_children_1() {
+    let root = tree_from(&[(0, 0), (1, 3), (2, 2), (2, 1), (1, 1), (1, 3), (2, 4)]);
+    root.reverse_children();
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[
+            (0, 0),
+            (1, 3),
+            (2, 4),
+            (1, 1),
+            (1, 3),
+            (2, 2),
+            (2, 1)
+        ]))
+    );
+
+    // Sorting is stable.
+    root.sort_children_by(|a, b| a.cmp(b));
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[
+            (0, 0),
+            (1, 1),
+            (1, 3),
+            (2, 4),
+            (1, 3),
+            (2, 2),
+            (2, 1)
+        ]))
+    );
+
+    root.sort_descendants_by(|a, b| b.cmp(a));
+    assert_links(&root);
+    assert_eq!(
+        tree_to_string(&root),
+        tree_to_string(&tree_from(&[
+            (0, 0),
+            (1, 3),
+            (2, 4),
+            (1, 3),
+            (2, 2),
+            (2, 1),
+            (1, 1)
+        ]))
+    );
+}
+
+#[test]
+fn sort_children_2() {
+    let root = Node::new(0);
+    root.append_all((0..50000).map(|i| Node::new((i * 7919) % 50000)));
+    root.sort_children_by(|a, b| a.cmp(b));
+    assert!(root.children().map(|node| *node.borrow()).eq(0..50000));
+}
+
+#[test]
+fn observer_bulk() {
+    let root = tree_from(&[(0, 0), (1, 2), (1, 1), (1, 3)]);
+    let observer = MutationObserver::new();
+    observer.observe(&root, false);
+
+    root.sort_children_by(|a, b| a.cmp(b));
+    root.split_children_at(2, &Node::new(4));
+    let children = root.take_children();
+    root.append_all(children);
+    assert_eq!(
+        take_records(&observer),
+        &[
+            "0: +[1,2,3] -[2,1,3] - -",
+            "0: +[] -[3] 2 -",
+            "0: +[] -[1,2] - -",
+            "0: +[1,2] -[] - -",
+        ]
+    );
+}
+
+#[test]
+fn breadth_first_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 3), (2, 4), (3, 6), (1, 2), (2, 5)]);
+    let data: Vec<_> = root.breadth_first().map(|node| *node.borrow()).collect();
+    assert_eq!(data, &[0, 1, 2, 3, 4, 5, 6]);
+
+    let levels: Vec<Vec<_>> = root
+        .levels()
+        .map(|level| level.iter().map(|node| *node.borrow()).collect())
+        .collect();
+    assert_eq!(levels, vec![vec![0], vec![1, 2], vec![3, 4, 5], vec![6]]);
+
+    let leaf = Node::new(7);
+    assert_eq!(leaf.breadth_first().count(), 1);
+    assert_eq!(leaf.levels().count(), 1);
+}
+
+#[test]
+fn breadth_first_2() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 3), (1, 2), (2, 4)]);
+    let other = Node::new(5);
+
+    let mut iter = root.breadth_first();
+    assert_eq!(iter.next(), Some(root.clone()));
+    let node1 = iter.next().unwrap();
+    assert_eq!(*node1.borrow(), 1);
+
+    // The next node is moved away, so the iteration stops.
+    other.append(node1.next_sibling().unwrap());
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next(), None);
+}
+
+fn to_data<I: Iterator<Item = Node<i32>>>(nodes: I) -> Vec<i32> {
+    nodes.map(|node| *node.borrow()).collect()
+}
+
+#[test]
+fn following_1() {
+    let (root, nodes) = sample_tree();
+
+    assert_eq!(to_data(nodes[1].following()), &[5, 6]);
+    assert_eq!(to_data(nodes[2].following()), &[3, 4, 5, 6]);
+    assert_eq!(to_data(nodes[4].following()), &[5, 6]);
+    assert_eq!(to_data(nodes[6].following()), &[]);
+    assert_eq!(to_data(root.following()), &[]);
+}
+
+#[test]
+fn preceding_1() {
+    let (root, nodes) = sample_tree();
+
+    assert_eq!(to_data(nodes[6].preceding()), &[4, 3, 2, 1]);
+    assert_eq!(to_data(nodes[5].preceding()), &[4, 3, 2, 1]);
+    assert_eq!(to_data(nodes[4].preceding()), &[2]);
+    assert_eq!(to_data(nodes[1].preceding()), &[]);
+    assert_eq!(to_data(root.preceding()), &[]);
+}
+
+#[test]
+fn tree_order_1() {
+    let (root, nodes) = sample_tree();
+
+    let mut forward = vec![root.clone()];
+    while let Some(node) = forward.last().unwrap().next_in_tree_order(&root) {
+        forward.push(node);
+    }
+    assert_eq!(forward, nodes);
+
+    let mut backward = vec![nodes[6].clone()];
+    while let Some(node) = backward.last().unwrap().previous_in_tree_order(&root) {
+        backward.push(node);
+    }
+    backward.reverse();
+    assert_eq!(backward, nodes);
+
+    assert_eq!(nodes[4].next_in_tree_order(&nodes[1]), None);
+    assert_eq!(nodes[4].next_in_tree_order(&nodes[4]), None);
+    assert_eq!(
+        nodes[3].next_in_tree_order(&nodes[3]),
+        Some(nodes[4].clone())
+    );
+    assert_eq!(
+        nodes[4].previous_in_tree_order(&nodes[3]),
+        Some(nodes[3].clone())
+    );
+    assert_eq!(nodes[3].previous_in_tree_order(&nodes[3]), None);
+    assert_eq!(
+        nodes[5].previous_in_tree_order(&nodes[6]),
+        Some(nodes[4].clone())
+    );
+}
+
+#[test]
+fn compare_document_position_1() {
+    let (root, nodes) = sample_tree();
+    let position = |a: usize, b: usize| nodes[a].compare_document_position(&nodes[b]);
+
+    assert!(position(3, 3).is_empty());
+    assert_eq!(
+        position(4, 1),
+        DocumentPosition::CONTAINS | DocumentPosition::PRECEDING
+    );
+    assert_eq!(
+        position(1, 4),
+        DocumentPosition::CONTAINED_BY | DocumentPosition::FOLLOWING
+    );
+    assert_eq!(position(2, 3), DocumentPosition::FOLLOWING);
+    assert_eq!(position(3, 2), DocumentPosition::PRECEDING);
+    assert_eq!(position(4, 6), DocumentPosition::FOLLOWING);
+    assert_eq!(position(6, 2), DocumentPosition::PRECEDING);
+    assert_eq!(position(6, 0).bits(), 10);
+
+    let other = Node::new(7);
+    let forward = root.compare_document_position(&other);
+    let backward = other.compare_document_position(&nodes[4]);
+    assert!(forward
+        .contains(DocumentPosition::DISCONNECTED | DocumentPosition::IMPLEMENTATION_SPECIFIC));
+    assert!(backward.contains(DocumentPosition::DISCONNECTED));
+    assert_eq!(
+        forward.contains(DocumentPosition::FOLLOWING),
+        backward.contains(DocumentPosition::PRECEDING)
+    );
+}
+
+#[test]
+fn sort_in_document_order_1() {
+    let (_, nodes) = sample_tree();
+    let mut shuffled: Vec<_> = [6, 2, 0, 4, 2, 5, 1, 6, 3]
+        .iter()
+        .map(|&i| nodes[i].clone())
+        .collect();
+
+    let mut sorted = shuffled.clone();
+    sorted.sort_by(Node::cmp_document_order);
+    sorted.dedup();
+    assert_eq!(sorted, nodes);
+
+    Node::sort_in_document_order(&mut shuffled);
+    assert_eq!(shuffled, nodes);
+
+    let other = tree_from(&[(0, 10), (1, 11)]);
+    let mut mixed = vec![
+        other.first_child().unwrap(),
+        nodes[3].clone(),
+        other.clone(),
+        nodes[1].clone(),
+    ];
+    let mut expected = mixed.clone();
+    expected.sort_by(Node::cmp_document_order);
+    Node::sort_in_document_order(&mut mixed);
+    assert_eq!(mixed, expected);
+    let data = to_data(mixed.into_iter());
+    assert!(data == [1, 3, 10, 11] || data == [10, 11, 1, 3]);
+}
+
+#[test]
+fn sort_in_document_order_stack_overflow() {
+    let root = deep_chain(100_000);
+    let mut nodes: Vec<_> = root.descendants().collect();
+    nodes.reverse();
+    assert_eq!(
+        nodes[0].compare_document_position(&root),
+        DocumentPosition::CONTAINS | DocumentPosition::PRECEDING
+    );
+
+    Node::sort_in_document_order(&mut nodes);
+    assert_eq!(nodes[0], root);
+    assert_eq!(nodes.len(), 100_000);
+}
+
+#[test]
+fn ancestry_1() {
+    let (root, nodes) = sample_tree();
+
+    assert_eq!(nodes[4].root(), root);
+    assert_eq!(root.root(), root);
+
+    assert!(nodes[1].contains(&nodes[4]));
+    assert!(nodes[1].contains(&nodes[1]));
+    assert!(!nodes[4].contains(&nodes[1]));
+    assert!(!nodes[5].contains(&nodes[4]));
+
+    assert!(nodes[1].is_ancestor_of(&nodes[4]));
+    assert!(!nodes[1].is_ancestor_of(&nodes[1]));
+    assert!(nodes[4].is_descendant_of(&root));
+    assert!(!nodes[4].is_descendant_of(&nodes[4]));
+    assert!(!nodes[4].is_descendant_of(&nodes[2]));
+}
+
+#[test]
+fn common_ancestor_1() {
+    let (_, nodes) = sample_tree();
+    let common = |a: usize, b: usize| {
+        nodes[a]
+            .common_ancestor(&nodes[b])
+            .map(|node| *node.borrow())
+    };
+
+    assert_eq!(common(2, 4), Some(1));
+    assert_eq!(common(4, 2), Some(1));
+    assert_eq!(common(4, 3), Some(3));
+    assert_eq!(common(4, 6), Some(0));
+    assert_eq!(common(5, 5), Some(5));
+    assert_eq!(nodes[4].common_ancestor(&Node::new(7)), None);
+
+    let common_of = |indices: &[usize]| {
+        Node::common_ancestor_of(indices.iter().map(|&i| &nodes[i])).map(|node| *node.borrow())
+    };
+
+    assert_eq!(common_of(&[4]), Some(4));
+    assert_eq!(common_of(&[4, 2]), Some(1));
+    assert_eq!(common_of(&[4, 2, 3]), Some(1));
+    assert_eq!(common_of(&[2, 4, 6]), Some(0));
+    assert_eq!(common_of(&[6, 5]), Some(5));
+    assert_eq!(common_of(&[]), None);
+
+    let other = Node::new(7);
+    assert_eq!(Node::common_ancestor_of(vec![&nodes[1], &other]), None);
+}
+
+#[test]
+fn common_ancestor_stack_overflow() {
+    let root = deep_chain(100_000);
+    let leaf = root.descendants().last().unwrap();
+    let middle = root.descendants().nth(50_000).unwrap();
+    let branch = Node::new(-1);
+    middle.append(branch.clone());
+
+    assert_eq!(leaf.root(), root);
+    assert!(middle.is_ancestor_of(&leaf));
+    assert_eq!(leaf.common_ancestor(&branch), Some(middle.clone()));
+    assert_eq!(
+        Node::common_ancestor_of(vec![&leaf, &branch, &leaf]),
+        Some(middle)
+    );
+}
+
+#[test]
+fn display_tree_1() {
+    let (root, _) = sample_tree();
+
+    assert_eq!(
+        root.display_tree().to_string(),
+        "0
+├── 1
+│   ├── 2
+│   └── 3
+│       └── 4
+└── 5
+    └── 6"
+    );
+    assert_eq!(format!("{:#?}", root), root.display_tree().to_string());
+    assert_eq!(format!("{:?}", root), "0");
+
+    assert_eq!(
+        root.display_tree().max_depth(1).to_string(),
+        "0
+├── 1
+│   └── ... (2 more)
+└── 5
+    └── ... (1 more)"
+    );
+    assert_eq!(
+        root.display_tree_with(|data| format!("<{}>", data))
+            .max_children(1)
+            .to_string(),
+        "<0>
+├── <1>
+│   ├── <2>
+│   └── ... (1 more)
+└── ... (1 more)"
+    );
+    assert_eq!(
+        root.display_tree().max_depth(0).to_string(),
+        "0
+└── ... (2 more)"
+    );
+    assert_eq!(Node::new(1).display_tree().to_string(), "1");
+}
+
+#[test]
+fn to_dot_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3)]);
+
+    assert_eq!(
+        root.to_dot(|data| data.to_string()),
+        "digraph {
+    ordering=out;
+    n0 [label=\"0\"];
+    n1 [label=\"1\"];
+    n2 [label=\"2\"];
+    n3 [label=\"3\"];
+    n0 -> n1;
+    n0 -> n3;
+    n1 -> n2;
+}
+"
+    );
+
+    let node = root.first_child().unwrap();
+    let dot = node
+        .dot(|data| format!("\"{}\"\n", data))
+        .back_links(true)
+        .highlight(vec![&node])
+        .to_string();
+    assert_eq!(
+        dot,
+        "digraph {
+    ordering=out;
+    n0 [label=\"\\\"1\\\"\\n\", style=filled, fillcolor=yellow];
+    n1 [label=\"\\\"2\\\"\\n\"];
+    n0 -> n1;
+    n0 -> n1 [style=dashed, constraint=false, label=\"last_child\"];
+}
+"
+    );
+
+    let dot = root
+        .dot(|data| data.to_string())
+        .back_links(true)
+        .to_string();
+    assert!(dot.contains("n3 -> n1 [style=dashed, constraint=false, label=\"previous_sibling\"];"));
+    assert!(dot.contains("n0 -> n3 [style=dashed, constraint=false, label=\"last_child\"];"));
+}
+
+#[test]
+fn post_order_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (2, 3), (1, 4), (2, 5)]);
+
+    assert_eq!(to_data(root.descendants().rev()), &[5, 4, 3, 2, 1, 0]);
+    assert_eq!(to_data(root.reverse_descendants()), &[5, 4, 3, 2, 1, 0]);
+    assert_eq!(to_data(root.post_order()), &[2, 3, 1, 5, 4, 0]);
+    assert_eq!(to_data(root.post_order().rev()), &[0, 4, 5, 1, 3, 2]);
+
+    let mut iter = root.descendants();
+    assert_eq!(iter.next(), Some(root.clone()));
+    assert_eq!(iter.next_back().map(|node| *node.borrow()), Some(5));
+    assert_eq!(to_data(iter), &[1, 2, 3, 4]);
+}
+
+fn edge_to_string(edge: &NodeEdge<i32>) -> String {
+    match *edge {
+        NodeEdge::Start(ref node) => format!("+{}", node),
+        NodeEdge::End(ref node) => format!("-{}", node),
+    }
+}
+
+#[test]
+fn skip_subtree_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (3, 3), (1, 4), (2, 5)]);
+
+    let mut edges = Vec::new();
+    let mut iter = root.traverse();
+    iter.skip_subtree();
+    while let Some(edge) = iter.next() {
+        edges.push(edge_to_string(&edge));
+        match edge {
+            NodeEdge::Start(ref node) if *node.borrow() == 1 || *node.borrow() == 5 => {
+                iter.skip_subtree();
+                iter.skip_subtree();
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(edges, &["+0", "+1", "-1", "+4", "+5", "-5", "-4", "-0"]);
+
+    let edges: Vec<_> = root
+        .traverse_filtered(|node| match *node.borrow() {
+            2 => Visit::SkipChildren,
+            5 => Visit::Stop,
+            _ => Visit::Continue,
+        })
+        .map(|edge| edge_to_string(&edge))
+        .collect();
+    assert_eq!(edges, &["+0", "+1", "+2", "-2", "-1", "+4"]);
+}
+
+#[test]
+fn skip_subtree_2() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (2, 3), (1, 4)]);
+    // The back cursor is inside the skipped subtree.
+    let mut iter = root.traverse();
+    assert_eq!(edge_to_string(&iter.next().unwrap()), "+0");
+    assert_eq!(edge_to_string(&iter.next().unwrap()), "+1");
+    iter.next_back();
+    iter.next_back();
+    iter.next_back();
+    assert_eq!(edge_to_string(&iter.next_back().unwrap()), "-1");
+    assert_eq!(edge_to_string(&iter.next_back().unwrap()), "-3");
+    iter.skip_subtree();
+    assert!(iter.next().is_none());
+    assert!(iter.next_back().is_none());
+
+    // The back cursor is outside of it.
+    let mut iter = root.traverse();
+    iter.next();
+    iter.next();
+    assert_eq!(edge_to_string(&iter.next_back().unwrap()), "-0");
+    iter.skip_subtree();
+    assert_eq!(edge_to_string(&iter.next_back().unwrap()), "-4");
+    let edges: Vec<_> = iter.map(|edge| edge_to_string(&edge)).collect();
+    assert_eq!(edges, &["-1", "+4"]);
+}
+
+#[test]
+fn traverse_with_depth_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (3, 3), (1, 4)]);
+
+    let edges: Vec<_> = root
+        .traverse_with_depth()
+        .map(|(depth, edge)| format!("{}{}", depth, edge_to_string(&edge)))
+        .collect();
+    assert_eq!(
+        edges,
+        &["0+0", "1+1", "2+2", "3+3", "3-3", "2-2", "1-1", "1+4", "1-4", "0-0"]
+    );
+
+    let edges: Vec<_> = root
+        .traverse_with_depth()
+        .max_depth(1)
+        .map(|(depth, edge)| format!("{}{}", depth, edge_to_string(&edge)))
+        .collect();
+    assert_eq!(edges, &["0+0", "1+1", "1-1", "1+4", "1-4", "0-0"]);
+}
+
+#[test]
+fn skip_subtree_deep() {
+    // Pruned subtrees are not visited at all.
+    let root = Node::new(0);
+    let mut prev = root.clone();
+    for i in 1..100000 {
+        let node = Node::new(i);
+        prev.append(node.clone());
+        prev = node;
+    }
+
+    let mut visited = 0;
+    let count = root
+        .traverse_filtered(|_| {
+            visited += 1;
+            Visit::SkipChildren
+        })
+        .count();
+    assert_eq!(count, 2);
+    assert_eq!(visited, 1);
+}
+
+struct Collector {
+    events: Vec<String>,
+}
+
+impl Visitor<i32> for Collector {
+    type Error = i32;
+
+    fn enter(&mut self, node: &Node<i32>) -> VisitResult<i32> {
+        self.events.push(format!("+{}", node));
+        match *node.borrow() {
+            1 => Ok(Visit::SkipChildren),
+            4 => Ok(Visit::Stop),
+            data if data < 0 => Err(data),
+            _ => Ok(Visit::Continue),
+        }
+    }
+
+    fn leave(&mut self, node: &Node<i32>) -> Result<(), i32> {
+        self.events.push(format!("-{}", node));
+        Ok(())
+    }
+}
+
+#[test]
+fn visitor_1() {
+    let root = tree_from(&[(0, 0), (1, 1), (2, 2), (1, 3), (2, 5), (1, 4), (1, 6)]);
+    let mut collector = Collector { events: Vec::new() };
+    assert_eq!(root.accept(&mut collector), Ok(()));
+    assert_eq!(
+        collector.events,
+        &["+0", "+1", "-1", "+3", "+5", "-5", "-3", "+4"]
+    );
+
+    let child = root.nth_child(1).unwrap();
+    *child.borrow_mut() = -1;
+    let mut collector = Collector { events: Vec::new() };
+    assert_eq!(root.accept(&mut collector), Err(-1));
+    assert_eq!(collector.events, &["+0", "+1", "-1", "+-1"]);
+}
+
+#[test]
+fn visitor_stack_overflow() {
+    struct Depth(usize, usize);
+
+    impl Visitor<i32> for Depth {
+        type Error = ();
+
+        fn enter(&mut self, _: &Node<i32>) -> VisitResult<()> {
+            self.0 += 1;
+            self.1 = std::cmp::max(self.0, self.1);
+            Ok(Visit::Continue)
+        }
+
+        fn leave(&mut self, _: &Node<i32>) -> Result<(), ()> {
+            self.0 -= 1;
+            Ok(())
+        }
+    }
+
+    let root = deep_chain(100000);
+
+    let mut depth = Depth(0, 0);
+    root.accept(&mut depth).unwrap();
+    assert_eq!(depth.1, 100000);
+}
+
+enum Xml {
+    Document,
+    Element(&'static str, Vec<(&'static str, &'static str)>),
+    Text(&'static str),
+}
+
+impl XPathData for Xml {
+    fn name(&self) -> Option<&str> {
+        match *self {
+            Xml::Element(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        match *self {
+            Xml::Element(_, ref attributes) => attributes
+                .iter()
+                .find(|attribute| attribute.0 == name)
+                .map(|attribute| attribute.1),
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> Option<&str> {
+        match *self {
+            Xml::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+fn element(
+    parent: &Node<Xml>,
+    name: &'static str,
+    attributes: Vec<(&'static str, &'static str)>,
+) -> Node<Xml> {
+    let node = Node::new(Xml::Element(name, attributes));
+    parent.append(node.clone());
+    node
+}
+
+fn item(parent: &Node<Xml>, id: &'static str, price: Option<&'static str>, text: &'static str) {
+    let mut attributes = vec![("id", id)];
+    attributes.extend(price.map(|price| ("price", price)));
+    element(parent, "item", attributes).append(Node::new(Xml::Text(text)));
+}
+
+// <library>
+//   <section id="a">
+//     <title>Rust</title>
+//     <item id="x" price="10">One</item>
+//     <item id="y" price="25">Two</item>
+//     <section id="b">
+//       <item id="z" price="5">Three</item>
+//     </section>
+//   </section>
+//   <item id="w">Four</item>
+// </library>
+fn library() -> Node<Xml> {
+    let root = Node::new(Xml::Document);
+    let library = element(&root, "library", Vec::new());
+    let a = element(&library, "section", vec![("id", "a")]);
+    element(&a, "title", Vec::new()).append(Node::new(Xml::Text("Rust")));
+    item(&a, "x", Some("10"), "One");
+    item(&a, "y", Some("25"), "Two");
+    let b = element(&a, "section", vec![("id", "b")]);
+    item(&b, "z", Some("5"), "Three");
+    item(&library, "w", None, "Four");
+    root
+}
+
+fn xml_label(node: &Node<Xml>) -> String {
+    match *node.borrow() {
+        Xml::Document => "/".to_string(),
+        Xml::Element(name, _) => match XPathData::attribute(&*node.borrow(), "id") {
+            Some(id) => format!("#{}", id),
+            None => name.to_string(),
+        },
+        Xml::Text(text) => format!("'{}'", text),
+    }
+}
+
+fn select(node: &Node<Xml>, expr: &str) -> Vec<String> {
+    xpath::select(node, expr)
+        .unwrap()
+        .iter()
+        .map(xml_label)
+        .collect()
+}
+
+#[test]
+fn xpath_paths() {
+    let root = library();
+    assert_eq!(select(&root, "//item"), &["#x", "#y", "#z", "#w"]);
+    assert_eq!(select(&root, "/library/section/item[2]"), &["#y"]);
+    assert_eq!(select(&root, "//section[@id='a']/child::*[2]"), &["#x"]);
+    assert_eq!(select(&root, "//item[2]"), &["#y"]);
+    assert_eq!(select(&root, "(//item)[last()]"), &["#w"]);
+    assert_eq!(select(&root, "(//item)[2]/text()"), &["'Two'"]);
+    assert_eq!(select(&root, "//item[@id='z']/../.."), &["#a"]);
+    assert_eq!(select(&root, "/"), &["/"]);
+    assert_eq!(select(&root, "/*"), &["library"]);
+    assert_eq!(select(&root, "//title/text()"), &["'Rust'"]);
+    assert_eq!(select(&root, "//section//item"), &["#x", "#y", "#z"]);
+    assert_eq!(
+        select(&root, "//item/ancestor::*"),
+        &["library", "#a", "#b"]
+    );
+    assert!(select(&root, "/item").is_empty());
+
+    let a = xpath::select(&root, "//section").unwrap()[0].clone();
+    assert_eq!(select(&a, "item"), &["#x", "#y"]);
+    assert_eq!(select(&a, ".//item"), &["#x", "#y", "#z"]);
+    assert_eq!(select(&a, "/library/item"), &["#w"]);
+
+    let path = XPath::parse("self::section/item[1]").unwrap();
+    assert_eq!(path.select(&a).len(), 1);
+    assert!(path.select(&root).is_empty());
+}
+
+#[test]
+fn xpath_axes() {
+    let root = library();
+    assert_eq!(
+        select(&root, "//item[@id='z']/ancestor::section"),
+        &["#a", "#b"]
+    );
+    assert_eq!(
+        select(&root, "//item[@id='z']/ancestor::section[1]"),
+        &["#b"]
+    );
+    assert_eq!(
+        select(&root, "//item[@id='z']/ancestor-or-self::*[2]"),
+        &["#b"]
+    );
+    assert_eq!(
+        select(&root, "//item[@id='x']/following-sibling::*"),
+        &["#y", "#b"]
+    );
+    assert_eq!(
+        select(&root, "//item[@id='y']/preceding-sibling::*"),
+        &["title", "#x"]
+    );
+    assert_eq!(
+        select(&root, "//item[@id='y']/preceding-sibling::*[1]"),
+        &["#x"]
+    );
+    assert_eq!(
+        select(&root, "//item[@id='x']/following::item"),
+        &["#y", "#z", "#w"]
+    );
+    assert_eq!(
+        select(&root, "//item[@id='z']/preceding::item"),
+        &["#x", "#y"]
+    );
+    assert_eq!(select(&root, "//item[@id='z']/preceding::*[1]"), &["#y"]);
+    assert_eq!(
+        select(&root, "//section/descendant::item[1]"),
+        &["#x", "#z"]
+    );
+    assert_eq!(select(&root, "//title/parent::section"), &["#a"]);
+    assert_eq!(select(&root, "//title/self::item"), &[] as &[&str]);
+}
+
+#[test]
+fn xpath_predicates() {
+    let root = library();
+    assert_eq!(select(&root, "//item[@id='x']"), &["#x"]);
+    assert_eq!(select(&root, "//item[@price > 8]"), &["#x", "#y"]);
+    assert_eq!(select(&root, "//item[@price = 10.0]"), &["#x"]);
+    assert_eq!(select(&root, "//item[@price != 10]"), &["#y", "#z"]);
+    assert_eq!(select(&root, "//item[not(@price)]"), &["#w"]);
+    assert_eq!(select(&root, "//item[@price * 2 >= 20]"), &["#x", "#y"]);
+    assert_eq!(select(&root, "//section[count(item) = 2]"), &["#a"]);
+    assert_eq!(select(&root, "//section[item/@price = 5]"), &["#b"]);
+    assert_eq!(select(&root, "//item[. = 'Two']"), &["#y"]);
+    assert_eq!(select(&root, "//*[name() = 'title']"), &["title"]);
+    assert_eq!(
+        select(&root, "//item[starts-with(@id, 'y') or contains(., 'hre')]"),
+        &["#y", "#z"]
+    );
+    assert_eq!(
+        select(&root, "//item[position() mod 2 = 1 and last() > 1]"),
+        &["#x"]
+    );
+    assert_eq!(select(&root, "(//item)[position() > 2][1]"), &["#z"]);
+    assert_eq!(select(&root, "//item[-(-2)]"), &["#y"]);
+    assert_eq!(
+        select(
+            &root,
+            "//item[string-length(normalize-space(' a  b ')) = 3]"
+        )
+        .len(),
+        4
+    );
+    assert_eq!(
+        select(&root, "//item[concat(@id, '-', string(@price)) = 'x-10']"),
+        &["#x"]
+    );
+    assert_eq!(
+        select(&root, "//section[string(.) = 'RustOneTwoThree']"),
+        &["#a"]
+    );
+    assert_eq!(select(&root, "//item[number(@id) = number(@id)]").len(), 0);
+    assert_eq!(select(&root, "//item[true() and boolean(@id)]").len(), 4);
+}
+
+#[test]
+fn xpath_errors() {
+    let error = |expr: &str| XPath::parse(expr).unwrap_err();
+
+    assert_eq!(error("//item[@id='x'").position, 14);
+    assert_eq!(error("//item | //title").position, 7);
+    assert_eq!(error("//item[foo::item]").position, 7);
+    assert_eq!(error("//item[$id]").position, 7);
+    assert_eq!(error("//item[@id = 'x]").position, 13);
+    assert_eq!(error("//item[count(1)]").position, 7);
+    assert_eq!(error("//item[unknown()]").position, 7);
+    assert_eq!(error("//item[not()]").position, 7);
+    assert_eq!(error("//item/@id").position, 0);
+    assert_eq!(error("1 + 2").position, 0);
+    assert_eq!(error("//item/comment()").position, 7);
+    assert_eq!(error("//item#").position, 6);
+    assert_eq!(
+        error("//item[").to_string(),
+        "unexpected end of expression at position 7"
+    );
+    assert!("//item[1]".parse::<XPath>().is_ok());
+}
+
+impl Element for Xml {
+    fn name(&self) -> Option<&str> {
+        XPathData::name(self)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        XPathData::attribute(self, name)
+    }
+}
+
+fn text_element(
+    parent: &Node<Xml>,
+    name: &'static str,
+    attributes: Vec<(&'static str, &'static str)>,
+    text: &'static str,
+) {
+    element(parent, name, attributes).append(Node::new(Xml::Text(text)));
+}
+
+// <html>
+//   <body>
+//     <div id="main" class="content wide">
+//       text
+//       <p class="intro">Hi</p>
+//       <p lang="en-US">One</p>
+//       <span>x</span>
+//       <p data-x="Hello World">Two</p>
+//     </div>
+//     <ul>
+//       <li>1</li> ... <li>5</li>
+//     </ul>
+//   </body>
+// </html>
+fn page() -> Node<Xml> {
+    let root = Node::new(Xml::Document);
+    let html = element(&root, "html", Vec::new());
+    let body = element(&html, "body", Vec::new());
+    let div = element(
+        &body,
+        "div",
+        vec![("id", "main"), ("class", "content wide")],
+    );
+    div.append(Node::new(Xml::Text("text")));
+    text_element(&div, "p", vec![("class", "intro")], "Hi");
+    text_element(&div, "p", vec![("lang", "en-US")], "One");
+    text_element(&div, "span", Vec::new(), "x");
+    text_element(&div, "p", vec![("data-x", "Hello World")], "Two");
+    let ul = element(&body, "ul", Vec::new());
+    for text in &["1", "2", "3", "4", "5"] {
+        text_element(&ul, "li", Vec::new(), text);
+    }
+
+    root
+}
+
+fn css_label(node: &Node<Xml>) -> String {
+    let name = Element::name(&*node.borrow()).unwrap().to_string();
+    match node.first_child() {
+        Some(child) => match *child.borrow() {
+            Xml::Text(text) => format!("{}({})", name, text),
+            _ => name,
+        },
+        None => name,
+    }
+}
+
+fn css(node: &Node<Xml>, selectors: &str) -> Vec<String> {
+    node.select(selectors)
+        .unwrap()
+        .iter()
+        .map(css_label)
+        .collect()
+}
+
+#[test]
+fn selectors_combinators() {
+    let root = page();
+    assert_eq!(css(&root, "p"), &["p(Hi)", "p(One)", "p(Two)"]);
+    assert_eq!(css(&root, "div > p.intro"), &["p(Hi)"]);
+    assert_eq!(css(&root, "#main .intro"), &["p(Hi)"]);
+    assert_eq!(css(&root, "html  body   p").len(), 3);
+    assert!(css(&root, "html > p").is_empty());
+    assert_eq!(css(&root, "p + span"), &["span(x)"]);
+    assert_eq!(css(&root, "span+p"), &["p(Two)"]);
+    assert_eq!(css(&root, "p ~ p"), &["p(One)", "p(Two)"]);
+    assert_eq!(css(&root, "body > * > p:first-child"), &["p(Hi)"]);
+    assert_eq!(css(&root, "span, li:first-child"), &["span(x)", "li(1)"]);
+    assert_eq!(css(&root, "*").len(), 13);
+
+    // Ancestors outside of the scope are matched too.
+    let div = root.select("div").unwrap()[0].clone();
+    assert_eq!(css(&div, "body p").len(), 3);
+    assert_eq!(css(&div, "div > span"), &["span(x)"]);
+    assert!(css(&div, "div").is_empty());
+}
+
+#[test]
+fn selectors_pseudo_classes() {
+    let root = page();
+    assert_eq!(css(&root, "li:first-child"), &["li(1)"]);
+    assert_eq!(css(&root, "li:last-child"), &["li(5)"]);
+    assert_eq!(css(&root, "li:nth-child(3)"), &["li(3)"]);
+    assert_eq!(
+        css(&root, "li:nth-child(2n+1)"),
+        &["li(1)", "li(3)", "li(5)"]
+    );
+    assert_eq!(
+        css(&root, "li:nth-child( odd )"),
+        &["li(1)", "li(3)", "li(5)"]
+    );
+    assert_eq!(css(&root, "li:nth-child(even)"), &["li(2)", "li(4)"]);
+    assert_eq!(css(&root, "li:nth-child(-n + 2)"), &["li(1)", "li(2)"]);
+    assert_eq!(css(&root, "li:nth-child(n+4)"), &["li(4)", "li(5)"]);
+    assert_eq!(css(&root, "li:nth-last-child(2)"), &["li(4)"]);
+    assert!(css(&root, "li:nth-child(-1n-9223372036854775807)").is_empty());
+    assert_eq!(
+        css(&root, "li:nth-child(9223372036854775807n+1)"),
+        &["li(1)"]
+    );
+    assert_eq!(
+        css(&root, "p:first-child, p:last-child"),
+        &["p(Hi)", "p(Two)"]
+    );
+    assert_eq!(css(&root, ":only-child"), &["html", "body"]);
+    assert_eq!(css(&root, "div > :not(p)"), &["span(x)"]);
+    assert_eq!(
+        css(&root, "li:not(:first-child):not(:nth-child(n+3))"),
+        &["li(2)"]
+    );
+    assert_eq!(css(&root, "p:not(div > .intro, [lang])"), &["p(Two)"]);
+}
+
+#[test]
+fn selectors_attributes() {
+    let root = page();
+    assert_eq!(css(&root, "[lang]"), &["p(One)"]);
+    assert_eq!(css(&root, "[lang|=en]"), &["p(One)"]);
+    assert_eq!(css(&root, "[lang|=en-US]"), &["p(One)"]);
+    assert!(css(&root, "[lang|=e]").is_empty());
+    assert_eq!(css(&root, "[class~=wide]"), &["div(text)"]);
+    assert!(css(&root, "[class~=wid]").is_empty());
+    assert_eq!(css(&root, "[data-x^='Hello']"), &["p(Two)"]);
+    assert_eq!(css(&root, "[data-x$=World]"), &["p(Two)"]);
+    assert_eq!(css(&root, "[ data-x *= \"o W\" ]"), &["p(Two)"]);
+    assert_eq!(css(&root, "[data-x='hello world' i]"), &["p(Two)"]);
+    assert!(css(&root, "[data-x='hello world']").is_empty());
+    assert!(css(&root, "[class^='']").is_empty());
+    assert_eq!(css(&root, "div#main.content.wide"), &["div(text)"]);
+    assert!(css(&root, "div#main.narrow").is_empty());
+}
+
+#[test]
+fn selectors_matches() {
+    let root = page();
+    let div = root.select("div").unwrap()[0].clone();
+    assert_eq!(div.matches("body > div.wide"), Ok(true));
+    assert_eq!(div.matches("ul div"), Ok(false));
+    assert_eq!(div.first_child().unwrap().matches("*"), Ok(false));
+    assert_eq!(root.matches("*"), Ok(false));
+
+    let list: SelectorList = "li:nth-child(2)".parse().unwrap();
+    assert_eq!(list.select(&root).len(), 1);
+    assert!(list.matches(&root.select("li").unwrap()[1]));
+}
+
+#[test]
+fn selectors_errors() {
+    let error = |selectors: &str| SelectorList::parse(selectors).unwrap_err();
+
+    assert_eq!(error("").position, 0);
+    assert_eq!(error("div >").position, 5);
+    assert_eq!(error("p:hover").position, 2);
+    assert_eq!(error("a,,b").position, 2);
+    assert_eq!(error("[a=").position, 3);
+    assert_eq!(error("[a='b]").position, 3);
+    assert_eq!(error("li:nth-child(2n+)").position, 13);
+    assert_eq!(error("li:nth-child(n 2)").position, 13);
+    assert_eq!(error("div)").position, 3);
+    assert_eq!(
+        error(":not(p").to_string(),
+        "unexpected end of selector at position 6"
+    );
+    assert!(selectors::SelectorList::parse("a > b ~ c + d e").is_ok());
+}